@@ -1,66 +1,156 @@
 use proc_macro::TokenStream;
 use proc_macro2::Ident;
 use proc_macro2::TokenStream as TokenStream2;
-use quote::quote;
+use quote::{format_ident, quote};
 
+use syn::parse::Parser;
 use syn::parse_macro_input;
+use syn::punctuated::Punctuated;
+use syn::Attribute;
+use syn::Expr;
 use syn::ExprAssign;
+use syn::ExprLit;
+use syn::ExprPath;
+use syn::FnArg;
+use syn::GenericParam;
 use syn::ItemFn;
+use syn::Lit;
+use syn::Meta;
+use syn::MetaNameValue;
+use syn::Pat;
+use syn::PatIdent;
+use syn::PatType;
 use syn::Path;
 use syn::ReturnType;
+use syn::Signature;
+use syn::Token;
+
+use root::Root;
+use snapshot::Snapshot;
+
+mod root;
+mod snapshot;
+mod span_tree;
 
 #[proc_macro_attribute]
 pub fn test_span(attr: TokenStream, item: TokenStream) -> TokenStream {
     let test_fn = parse_macro_input!(item as ItemFn);
 
-    let macro_attrs = if attr.is_empty() {
-        quote! { test }
-    } else {
-        attr.into()
+    // #[test_span(tokio::test, snapshot)]: the test attribute, and options
+    let attr_metas = match Punctuated::<Meta, Token![,]>::parse_terminated.parse(attr) {
+        Ok(metas) => metas,
+        Err(e) => return e.to_compile_error().into(),
     };
-
-    let fn_attrs = &test_fn.attrs;
+    let mut macro_attrs = None;
+    let mut snapshot = None;
+    let mut watchdog = None;
+    let mut deny_unclosed_spans = false;
+    let mut deny_misplaced_exits = false;
+    let mut deny_unrecorded_fields = false;
+    for meta in attr_metas {
+        if meta.path().is_ident("snapshot") {
+            match Snapshot::from_meta(&meta) {
+                Ok(options) => snapshot = Some(options),
+                Err(e) => return e.to_compile_error().into(),
+            }
+        } else if meta.path().is_ident("watchdog") {
+            match parse_watchdog(&meta) {
+                Ok(millis) => watchdog = Some(millis),
+                Err(e) => return e.to_compile_error().into(),
+            }
+        } else if meta.path().is_ident("deny_unclosed_spans") {
+            if let Err(e) = parse_flag(&meta) {
+                return e.to_compile_error().into();
+            }
+            deny_unclosed_spans = true;
+        } else if meta.path().is_ident("deny_misplaced_exits") {
+            if let Err(e) = parse_flag(&meta) {
+                return e.to_compile_error().into();
+            }
+            deny_misplaced_exits = true;
+        } else if meta.path().is_ident("deny_unrecorded_fields") {
+            if let Err(e) = parse_flag(&meta) {
+                return e.to_compile_error().into();
+            }
+            deny_unrecorded_fields = true;
+        } else if macro_attrs.is_some() {
+            return syn::Error::new_spanned(
+                meta,
+                "expected a single test attribute, such as `tokio::test`",
+            )
+            .to_compile_error()
+            .into();
+        } else {
+            macro_attrs = Some(quote!(#meta));
+        }
+    }
 
     let mut level = quote!(::test_span::reexports::tracing::Level::INFO);
+    let mut level_attr = None;
 
     let mut target_directives: Vec<_> = Vec::new();
 
-    // Get tracing level from #[level(tracing::Level::INFO)]
-    let fn_attrs = fn_attrs
-        .iter()
-        .filter(|attr| {
-            let path = &attr.path();
-            match quote!(#path).to_string().as_str() {
-                "level" => {
-                    let value: Path = attr.parse_args().expect(
-                        "wrong level attribute syntax. Example: #[level(tracing::Level::INFO)]",
-                    );
-                    level = quote!(#value);
-                    false
-                }
-                "target" => {
-                    let value: ExprAssign = attr.parse_args().expect("each targetFilter directive expects a single assignment expression. example: #[targetFilter(apollo_router=debug)]");
-                    // foo = Level::INFO => .with_target("foo".to_string(), Level::INFO)
-                    let name = value.left;
-                    let mut target_name = quote!(#name).to_string();
-                    target_name.retain(|c| !c.is_whitespace());
-
-                    let target_value = value.right;
-
-                    target_directives.push(quote!(.with_target(#target_name .to_string(), #target_value)));
-
-                    false
-                }
-                _ => true,
+    let mut root = Root::default();
+    let mut root_attr = None;
+
+    let mut errors: Option<syn::Error> = None;
+    let mut push_error = |error: syn::Error| match &mut errors {
+        Some(errors) => errors.combine(error),
+        None => errors = Some(error),
+    };
+
+    // Take out #[level(tracing::Level::INFO)], #[target(my_crate = tracing::Level::DEBUG)], #[root(...)] and #[snapshot(...)]
+    let mut fn_attrs = Vec::new();
+    for attr in &test_fn.attrs {
+        if attr.path().is_ident("level") {
+            if level_attr.is_some() {
+                push_error(syn::Error::new_spanned(
+                    attr,
+                    "duplicate #[level] attribute",
+                ));
             }
-        })
-        .collect::<Vec<_>>();
+            level_attr = Some(attr);
+            match parse_level(attr) {
+                Ok(value) => level = quote!(#value),
+                Err(e) => push_error(e),
+            }
+        } else if attr.path().is_ident("target") {
+            match parse_target(attr) {
+                // foo = Level::INFO => .with_target("foo".to_string(), Level::INFO)
+                Ok((target_name, target_value)) => target_directives
+                    .push(quote!(.with_target(#target_name .to_string(), #target_value))),
+                Err(e) => push_error(e),
+            }
+        } else if attr.path().is_ident("root") {
+            if root_attr.is_some() {
+                push_error(syn::Error::new_spanned(attr, "duplicate #[root] attribute"));
+            }
+            root_attr = Some(attr);
+            match Root::from_attribute(attr) {
+                Ok(value) => root = value,
+                Err(e) => push_error(e),
+            }
+        } else if attr.path().is_ident("snapshot") {
+            match Snapshot::from_meta(&attr.meta) {
+                Ok(options) => snapshot = Some(options),
+                Err(e) => push_error(e),
+            }
+        } else {
+            fn_attrs.push(attr);
+        }
+    }
+
+    if let Some(errors) = errors {
+        return errors.to_compile_error().into();
+    }
 
     let maybe_async = &test_fn.sig.asyncness;
 
     let body = &test_fn.block;
     let test_name = &test_fn.sig.ident;
     let output_type = &test_fn.sig.output;
+    let generics = &test_fn.sig.generics;
+    let where_clause = &generics.where_clause;
 
     let maybe_semicolon = if let ReturnType::Default = output_type {
         quote! {;}
@@ -68,68 +158,387 @@ pub fn test_span(attr: TokenStream, item: TokenStream) -> TokenStream {
         quote! {}
     };
 
+    let arguments = match Arguments::new(&test_fn.sig) {
+        Ok(arguments) => arguments,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let Arguments {
+        outer,
+        inner,
+        forwarded,
+    } = &arguments;
+
+    // Parameterized tests get their #[test] from rstest or test_case, which expand once
+    // #[test_span] is done when they come after it. When they come before it,
+    // #[test_span] is applied to the cases they generate, already marked with #[test].
+    let has_test_attribute = fn_attrs.iter().any(|attr| {
+        attr.path().segments.last().is_some_and(|segment| {
+            ["test", "rstest", "case", "test_case"].contains(&segment.ident.to_string().as_str())
+        })
+    });
+    let (leading_test_attr, trailing_test_attr) = match macro_attrs {
+        // rstest needs to come before the async test attribute, so it can set up each case
+        Some(macro_attrs) if !outer.is_empty() => (None, Some(quote!(#[#macro_attrs]))),
+        Some(macro_attrs) => (Some(quote!(#[#macro_attrs])), None),
+        None if outer.is_empty() && !has_test_attribute => (Some(quote!(#[test])), None),
+        None => (None, None),
+    };
+
+    // Explicit generics let the inner function be called even when they can't be inferred.
+    let generic_arguments: Vec<_> = generics
+        .params
+        .iter()
+        .filter_map(|param| match param {
+            GenericParam::Type(param) => Some(&param.ident),
+            GenericParam::Const(param) => Some(&param.ident),
+            GenericParam::Lifetime(_) => None,
+        })
+        .collect();
+    let turbofish = if generic_arguments.is_empty() {
+        quote!()
+    } else {
+        quote!(::<#(#generic_arguments),*>)
+    };
+    let call = quote! {
+        #test_name #turbofish (#(#forwarded,)* &telemetry)
+    };
+
     let run_test = if maybe_async.is_some() {
-        async_test(test_name)
+        async_test(call)
     } else {
-        sync_test(test_name)
+        sync_test(call)
     };
 
     let ret = quote! {#output_type};
 
-    let subscriber_boilerplate = subscriber_boilerplate(level, target_directives);
+    let subscriber_boilerplate =
+        subscriber_boilerplate(level, target_directives, root.expand(test_name));
+    let telemetry_shim = telemetry_shim();
+
+    // dropped after the test ran, which dumps the telemetry if the test panicked
+    // and rewrites the coverage report if one was asked for
+    let failure_dump = quote! {
+        let _coverage_report = ::test_span::__CoverageReport;
+        let _failure_dump = ::test_span::FailureDump::new(concat!(module_path!(), "::", stringify!(#test_name)), get_telemetry);
+    };
+
+    // dropped after the test ran, which cancels it
+    let watchdog = watchdog.map(|millis| {
+        quote! {
+            let _watchdog = ::test_span::Watchdog::start(concat!(module_path!(), "::", stringify!(#test_name)), &telemetry, ::std::time::Duration::from_millis(#millis));
+        }
+    });
+
+    // what runs once the test body returned
+    let mut after_test = Vec::new();
+    if let Some(snapshot) = snapshot {
+        after_test.push(snapshot.expand(test_name));
+    }
+    if deny_unclosed_spans {
+        after_test.push(quote! {
+            if let Err(error) = ::test_span::__check_unclosed_spans(&telemetry) {
+                panic!("{}", error);
+            }
+        });
+    }
+    if deny_misplaced_exits {
+        after_test.push(quote! {
+            if let Err(error) = ::test_span::__check_enter_diagnostics(&telemetry) {
+                panic!("{}", error);
+            }
+        });
+    }
+    if deny_unrecorded_fields {
+        after_test.push(quote! {
+            if let Err(error) = ::test_span::__check_unrecorded_fields(&telemetry) {
+                panic!("{}", error);
+            }
+        });
+    }
+    let run_test = if after_test.is_empty() {
+        run_test
+    } else {
+        quote! {
+            let result = #run_test;
+            #(#after_test)*
+            result
+        }
+    };
 
     quote! {
-      #[#macro_attrs]
+      #leading_test_attr
       #(#fn_attrs)*
-      #maybe_async fn #test_name() #ret {
+      #trailing_test_attr
+      #maybe_async fn #test_name #generics (#(#outer),*) #ret #where_clause {
         use ::test_span::reexports::tracing::Instrument;
-        #maybe_async fn #test_name(get_telemetry: impl Fn() -> (::test_span::Span, ::test_span::Records), get_logs: impl Fn() -> ::test_span::Records, get_spans: impl Fn() -> ::test_span::Span) #ret
+        #[allow(clippy::too_many_arguments)]
+        #maybe_async fn #test_name #generics (#(#inner,)* telemetry: &::test_span::TestTelemetry) #ret #where_clause {
+          #telemetry_shim
+
           #body
+        }
 
 
         #subscriber_boilerplate
 
+        #failure_dump
+
+        #watchdog
+
         #run_test #maybe_semicolon
       }
     }
     .into()
 }
 
-fn async_test(test_name: &Ident) -> TokenStream2 {
+/// Builds a `test_span::SpanPattern` from a nested description of the expected tree:
+///
+/// ```ignore
+/// let pattern = span_tree! {
+///     "root" {
+///         "my_crate::do_stuff"(number = 52) {
+///             "do_stuff2" { event!(INFO, "here i am!") }
+///         }
+///         // `..` matches a span at any depth, `_` matches any name
+///         .. "cleanup" { event!(WARN, "retrying", retries = 3) }
+///     }
+/// };
+/// assert_spans_match!(get_spans(), pattern);
+/// ```
+///
+/// Children and events are unordered, unless the span is annotated with `#[ordered]`.
+#[proc_macro]
+pub fn span_tree(input: TokenStream) -> TokenStream {
+    let tree = parse_macro_input!(input as span_tree::Node);
+    match tree.check_root() {
+        Ok(()) => tree.expand().into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+const LEVELS: [&str; 5] = ["TRACE", "DEBUG", "INFO", "WARN", "ERROR"];
+
+// #[level(tracing::Level::INFO)]
+fn parse_level(attr: &Attribute) -> syn::Result<Path> {
+    let level: Path = attr.parse_args().map_err(|e| {
+        syn::Error::new(
+            e.span(),
+            "expected a tracing level. example: #[level(tracing::Level::INFO)]",
+        )
+    })?;
+    check_level(&level)?;
+    Ok(level)
+}
+
+fn check_level(level: &Path) -> syn::Result<()> {
+    let last = level
+        .segments
+        .last()
+        .expect("paths have at least a segment; qed");
+    if last.arguments.is_empty() && LEVELS.contains(&last.ident.to_string().as_str()) {
+        Ok(())
+    } else {
+        Err(syn::Error::new_spanned(
+            level,
+            "unknown level, expected one of tracing::Level::TRACE, DEBUG, INFO, WARN or ERROR",
+        ))
+    }
+}
+
+// #[target(my_crate::module = tracing::Level::DEBUG)]
+fn parse_target(attr: &Attribute) -> syn::Result<(String, Path)> {
+    let ExprAssign { left, right, .. } = attr.parse_args().map_err(|e| {
+        syn::Error::new(
+            e.span(),
+            "expected a target directive. example: #[target(my_crate::module = tracing::Level::DEBUG)]",
+        )
+    })?;
+
+    let target_name = match *left {
+        Expr::Path(ExprPath {
+            qself: None,
+            ref path,
+            ..
+        }) if path.leading_colon.is_none()
+            && path
+                .segments
+                .iter()
+                .all(|segment| segment.arguments.is_empty()) =>
+        {
+            path.segments
+                .iter()
+                .map(|segment| segment.ident.to_string())
+                .collect::<Vec<_>>()
+                .join("::")
+        }
+        left => {
+            return Err(syn::Error::new_spanned(
+                left,
+                "expected a target name, such as `my_crate` or `my_crate::module`",
+            ))
+        }
+    };
+
+    let level = match *right {
+        Expr::Path(ExprPath {
+            qself: None, path, ..
+        }) => path,
+        right => {
+            return Err(syn::Error::new_spanned(
+                right,
+                "expected a tracing level, such as `tracing::Level::DEBUG`",
+            ))
+        }
+    };
+    check_level(&level)?;
+
+    Ok((target_name, level))
+}
+
+// deny_unclosed_spans, deny_misplaced_exits, deny_unrecorded_fields: options that are on once named
+fn parse_flag(meta: &Meta) -> syn::Result<()> {
+    match meta {
+        Meta::Path(_) => Ok(()),
+        _ => {
+            let name = meta
+                .path()
+                .get_ident()
+                .map(ToString::to_string)
+                .unwrap_or_default();
+            Err(syn::Error::new_spanned(
+                meta,
+                format!("`{name}` doesn't take a value"),
+            ))
+        }
+    }
+}
+
+// watchdog = "30s", in milliseconds
+fn parse_watchdog(meta: &Meta) -> syn::Result<u64> {
+    let error = |span: &dyn quote::ToTokens| {
+        syn::Error::new_spanned(
+            span,
+            "expected a duration such as `watchdog = \"30s\"`, in ms, s, m or h",
+        )
+    };
+    let Meta::NameValue(MetaNameValue {
+        value: Expr::Lit(ExprLit {
+            lit: Lit::Str(duration),
+            ..
+        }),
+        ..
+    }) = meta
+    else {
+        return Err(error(meta));
+    };
+
+    let value = duration.value();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let millis = match unit.trim() {
+        "ms" => 1,
+        "s" => 1_000,
+        "m" => 60_000,
+        "h" => 3_600_000,
+        _ => return Err(error(duration)),
+    };
+    amount
+        .parse::<u64>()
+        .ok()
+        .and_then(|amount| amount.checked_mul(millis))
+        .filter(|millis| *millis > 0)
+        .ok_or_else(|| error(duration))
+}
+
+/// The test arguments: as the test function takes them, as the inner function takes them,
+/// and how the former are passed to the latter.
+struct Arguments {
+    outer: Vec<TokenStream2>,
+    inner: Vec<TokenStream2>,
+    forwarded: Vec<Ident>,
+}
+
+impl Arguments {
+    fn new(signature: &Signature) -> syn::Result<Self> {
+        let mut arguments = Self {
+            outer: Vec::new(),
+            inner: Vec::new(),
+            forwarded: Vec::new(),
+        };
+        for (index, input) in signature.inputs.iter().enumerate() {
+            let FnArg::Typed(PatType { attrs, pat, ty, .. }) = input else {
+                return Err(syn::Error::new_spanned(
+                    input,
+                    "#[test_span] tests can't take `self`",
+                ));
+            };
+            // rstest injects fixtures by name, so named arguments keep their name
+            let forwarded = match &**pat {
+                Pat::Ident(PatIdent {
+                    ident,
+                    subpat: None,
+                    ..
+                }) => ident.clone(),
+                _ => format_ident!("__test_span_argument_{}", index),
+            };
+            // attributes such as #[case] are for the test function
+            arguments.outer.push(quote!(#(#attrs)* #forwarded: #ty));
+            arguments.inner.push(quote!(#pat: #ty));
+            arguments.forwarded.push(forwarded);
+        }
+        Ok(arguments)
+    }
+}
+
+fn async_test(call: TokenStream2) -> TokenStream2 {
     quote! {
-        #test_name(get_telemetry, get_logs, get_spans)
-            .instrument(root_span).await
+        #call.instrument(root_span).await
     }
 }
 
-fn sync_test(test_name: &Ident) -> TokenStream2 {
+fn sync_test(call: TokenStream2) -> TokenStream2 {
     quote! {
-        root_span.in_scope(|| {
-            #test_name(get_telemetry, get_logs, get_spans)
-        });
+        root_span.in_scope(|| #call)
     }
 }
+/// The closures tests used before `telemetry` was injected, `telemetry` must be in scope.
+fn telemetry_shim() -> TokenStream2 {
+    quote! {
+        #[allow(unused)]
+        let get_telemetry = || telemetry.telemetry();
+
+        #[allow(unused)]
+        let get_logs = || telemetry.logs();
+
+        #[allow(unused)]
+        let get_spans = || telemetry.spans();
+
+        #[allow(unused)]
+        let get_report = || telemetry.report();
+    }
+}
+
 fn subscriber_boilerplate(
     level: TokenStream2,
     target_directives: Vec<TokenStream2>,
+    root_span: TokenStream2,
 ) -> TokenStream2 {
+    let telemetry_shim = telemetry_shim();
     quote! {
         let filter = ::test_span::Filter::new(#level) #(#target_directives)*;
 
         ::test_span::init();
 
-        let root_span = ::test_span::reexports::tracing::span!(#level, "root");
+        let root_span = #root_span;
 
         let root_id = root_span.id().clone().expect("couldn't get root span id; this cannot happen.");
 
-        #[allow(unused)]
-        let get_telemetry = || ::test_span::get_telemetry_for_root(&root_id, &filter);
-
-        #[allow(unused)]
-        let get_logs = || ::test_span::get_logs_for_root(&root_id, &filter);
+        let telemetry = ::test_span::TestTelemetry::new(root_id, filter);
 
+        let _registered_test = ::test_span::__register_test(&telemetry);
 
-        #[allow(unused)]
-        let get_spans = || ::test_span::get_spans_for_root(&root_id, &filter);
+        #telemetry_shim
     }
 }
@@ -0,0 +1,65 @@
+//! `#[root(...)]`, which configures the span every `#[test_span]` test runs in.
+//!
+//! ```text
+//! #[root(name = "checkout_flow", level = INFO, fields(test = "checkout", user.id = 42))]
+//! ```
+use proc_macro2::{Ident, TokenStream as TokenStream2};
+use quote::quote;
+use syn::meta::ParseNestedMeta;
+use syn::{parenthesized, Attribute, LitStr, Path};
+
+use crate::check_level;
+
+#[derive(Default)]
+pub(crate) struct Root {
+    name: Option<LitStr>,
+    level: Option<Path>,
+    fields: Option<TokenStream2>,
+}
+
+impl Root {
+    pub(crate) fn from_attribute(attr: &Attribute) -> syn::Result<Self> {
+        let mut root = Self::default();
+        attr.parse_nested_meta(|meta| root.parse_option(meta))?;
+        Ok(root)
+    }
+
+    fn parse_option(&mut self, meta: ParseNestedMeta) -> syn::Result<()> {
+        if meta.path.is_ident("name") {
+            self.name = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("level") {
+            let level: Path = meta.value()?.parse()?;
+            check_level(&level)?;
+            self.level = Some(level);
+        } else if meta.path.is_ident("fields") {
+            // handed to tracing::span! as is, so they take the same syntax
+            let content;
+            parenthesized!(content in meta.input);
+            self.fields = Some(content.parse()?);
+        } else {
+            return Err(
+                meta.error("unknown root option, expected one of `name`, `level` or `fields`")
+            );
+        }
+        Ok(())
+    }
+
+    /// The root span, named after the test unless told otherwise.
+    pub(crate) fn expand(&self, test_name: &Ident) -> TokenStream2 {
+        let name = self
+            .name
+            .as_ref()
+            .map(|name| quote!(#name))
+            .unwrap_or_else(|| quote!(stringify!(#test_name)));
+        let level = match &self.level {
+            // `level = INFO` is short for the tracing level
+            Some(level) if level.get_ident().is_some() => {
+                quote!(::test_span::reexports::tracing::Level::#level)
+            }
+            Some(level) => quote!(#level),
+            None => quote!(::test_span::reexports::tracing::Level::INFO),
+        };
+        let fields = self.fields.as_ref().map(|fields| quote!(, #fields));
+        quote!(::test_span::reexports::tracing::span!(#level, #name #fields))
+    }
+}
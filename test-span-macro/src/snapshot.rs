@@ -0,0 +1,139 @@
+//! `#[test_span(snapshot)]` and `#[snapshot(...)]`, which snapshot the telemetry once the test body returns.
+//!
+//! ```text
+//! #[snapshot(spans, logs, format = "text", redact("timestamp", "request.id"), sorted, backend = "golden", dir = "tests/golden")]
+//! ```
+use proc_macro2::{Ident, TokenStream as TokenStream2};
+use quote::quote;
+use syn::meta::ParseNestedMeta;
+use syn::punctuated::Punctuated;
+use syn::{parenthesized, LitStr, Meta, Token};
+
+#[derive(Default)]
+pub(crate) struct Snapshot {
+    spans: bool,
+    logs: bool,
+    text: bool,
+    redactions: Vec<LitStr>,
+    sorted: bool,
+    golden: bool,
+    dir: Option<LitStr>,
+}
+
+impl Snapshot {
+    /// Parses `snapshot` or `snapshot(...)`.
+    pub(crate) fn from_meta(meta: &Meta) -> syn::Result<Self> {
+        let mut snapshot = Self::default();
+        match meta {
+            Meta::Path(_) => {}
+            Meta::List(list) => {
+                list.parse_nested_meta(|meta| snapshot.parse_option(meta))?;
+                if let (Some(dir), false) = (&snapshot.dir, snapshot.golden) {
+                    return Err(syn::Error::new_spanned(
+                        dir,
+                        "`dir` only applies to golden files, add `backend = \"golden\"`",
+                    ));
+                }
+            }
+            Meta::NameValue(_) => {
+                return Err(syn::Error::new_spanned(
+                    meta,
+                    "expected `snapshot` or `snapshot(spans, logs, format = \"text\")`",
+                ))
+            }
+        }
+        Ok(snapshot)
+    }
+
+    fn parse_option(&mut self, meta: ParseNestedMeta) -> syn::Result<()> {
+        if meta.path.is_ident("spans") {
+            self.spans = true;
+        } else if meta.path.is_ident("logs") {
+            self.logs = true;
+        } else if meta.path.is_ident("sorted") {
+            self.sorted = true;
+        } else if meta.path.is_ident("format") {
+            let format: LitStr = meta.value()?.parse()?;
+            match format.value().as_str() {
+                "json" => self.text = false,
+                "text" => self.text = true,
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        format,
+                        "unknown snapshot format, expected \"json\" or \"text\"",
+                    ))
+                }
+            }
+        } else if meta.path.is_ident("backend") {
+            let backend: LitStr = meta.value()?.parse()?;
+            match backend.value().as_str() {
+                "insta" => self.golden = false,
+                "golden" => self.golden = true,
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        backend,
+                        "unknown snapshot backend, expected \"insta\" or \"golden\"",
+                    ))
+                }
+            }
+        } else if meta.path.is_ident("dir") {
+            self.dir = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("redact") {
+            let content;
+            parenthesized!(content in meta.input);
+            self.redactions
+                .extend(Punctuated::<LitStr, Token![,]>::parse_terminated(&content)?);
+        } else {
+            return Err(meta.error(
+                "unknown snapshot option, expected one of `spans`, `logs`, `format`, `redact`, `sorted`, `backend` or `dir`",
+            ));
+        }
+        Ok(())
+    }
+
+    /// The statements that snapshot the telemetry, `get_telemetry` must be in scope.
+    pub(crate) fn expand(&self, test_name: &Ident) -> TokenStream2 {
+        let redactions = &self.redactions;
+        let sorted = self.sorted.then(|| quote!(.sorted()));
+        let format = if self.text {
+            quote!(::test_span::SnapshotFormat::Text)
+        } else {
+            quote!(::test_span::SnapshotFormat::Json)
+        };
+        let both = !self.spans && !self.logs;
+
+        // logs come first, which keeps the snapshot names of tests that used to call insta by hand
+        let snapshots = [("logs", self.logs || both), ("spans", self.spans || both)]
+            .into_iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(kind, _)| {
+                let value = Ident::new(kind, proc_macro2::Span::call_site());
+                if self.golden {
+                    let dir = self
+                        .dir
+                        .as_ref()
+                        .map(|dir| quote!(#dir))
+                        .unwrap_or_else(|| quote!("tests/golden"));
+                    quote! {
+                        ::test_span::assert_golden(
+                            ::test_span::__golden_path(env!("CARGO_MANIFEST_DIR"), #dir, module_path!(), stringify!(#test_name), #kind, #format),
+                            &#format.render(&#value),
+                        );
+                    }
+                } else if self.text {
+                    quote!(::test_span::__insta_snapshot!(text, #value);)
+                } else {
+                    quote!(::test_span::__insta_snapshot!(json, #value);)
+                }
+            });
+
+        quote! {
+            {
+                let options = ::test_span::SnapshotOptions::new() #(.redact(#redactions))* #sorted;
+                let (spans, logs) = get_telemetry();
+                let (spans, logs) = (options.spans(spans), options.logs(logs));
+                #(#snapshots)*
+            }
+        }
+    }
+}
@@ -0,0 +1,234 @@
+//! The `span_tree!` DSL, which expands to a `::test_span::SpanPattern`.
+//!
+//! ```text
+//! tree  := node
+//! node  := ("#[ordered]")? ".."? (LitStr | "_") ("(" field,* ")")? ("{" (node | event),* "}")?
+//! event := "event!" "(" LEVEL ("," LitStr)? ("," field)* ")"
+//! field := (ident ("." ident)* | LitStr) "=" expr
+//! ```
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{braced, parenthesized, Attribute, Expr, Ident, LitStr, Token};
+
+pub(crate) struct Node {
+    name: Option<LitStr>,
+    descendant: Option<Token![..]>,
+    ordered: bool,
+    fields: Vec<Field>,
+    items: Vec<Item>,
+}
+
+enum Item {
+    Node(Node),
+    Event(Event),
+}
+
+struct Event {
+    level: TokenStream2,
+    message: Option<LitStr>,
+    fields: Vec<Field>,
+}
+
+struct Field {
+    name: String,
+    value: Expr,
+}
+
+impl Parse for Node {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut ordered = false;
+        for attribute in input.call(Attribute::parse_outer)? {
+            if attribute.path().is_ident("ordered") && attribute.meta.require_path_only().is_ok() {
+                ordered = true;
+            } else {
+                return Err(syn::Error::new_spanned(
+                    attribute,
+                    "unknown span attribute, the only supported attribute is #[ordered]",
+                ));
+            }
+        }
+
+        let descendant = input.parse::<Option<Token![..]>>()?;
+
+        let name = if input.parse::<Option<Token![_]>>()?.is_some() {
+            None
+        } else if input.peek(LitStr) {
+            Some(input.parse::<LitStr>()?)
+        } else {
+            return Err(input.error(
+                "expected a span name string literal, or `_` to match any span. example: \"my_crate::do_stuff\"",
+            ));
+        };
+
+        let mut fields = Vec::new();
+        if input.peek(syn::token::Paren) {
+            let content;
+            parenthesized!(content in input);
+            fields = Punctuated::<Field, Token![,]>::parse_terminated(&content)?
+                .into_iter()
+                .collect();
+        }
+
+        let mut items = Vec::new();
+        if input.peek(syn::token::Brace) {
+            let content;
+            braced!(content in input);
+            while !content.is_empty() {
+                items.push(content.parse()?);
+                if !content.is_empty() {
+                    content.parse::<Option<Token![,]>>()?;
+                }
+            }
+        }
+
+        Ok(Self {
+            name,
+            descendant,
+            ordered,
+            fields,
+            items,
+        })
+    }
+}
+
+impl Parse for Item {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.peek(Ident) && input.peek2(Token![!]) {
+            let macro_name: Ident = input.parse()?;
+            if macro_name != "event" {
+                return Err(syn::Error::new_spanned(
+                    macro_name,
+                    "expected `event!(LEVEL, \"message\", field = value)`",
+                ));
+            }
+            input.parse::<Token![!]>()?;
+            let content;
+            parenthesized!(content in input);
+            Ok(Item::Event(content.parse()?))
+        } else {
+            Ok(Item::Node(input.parse()?))
+        }
+    }
+}
+
+impl Parse for Event {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let level: Ident = input.parse().map_err(|e| {
+            syn::Error::new(
+                e.span(),
+                "expected an event level: TRACE, DEBUG, INFO, WARN or ERROR",
+            )
+        })?;
+        let level = match level.to_string().as_str() {
+            "TRACE" | "DEBUG" | "INFO" | "WARN" | "ERROR" => {
+                quote!(::test_span::reexports::tracing::Level::#level)
+            }
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    level,
+                    "unknown level, expected one of TRACE, DEBUG, INFO, WARN or ERROR",
+                ))
+            }
+        };
+
+        let mut message = None;
+        let mut fields = Vec::new();
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+            if input.peek(LitStr) && !input.peek2(Token![=]) {
+                if message.is_some() || !fields.is_empty() {
+                    return Err(input.error("the message must come right after the level"));
+                }
+                message = Some(input.parse()?);
+            } else {
+                fields.push(input.parse()?);
+            }
+        }
+
+        Ok(Self {
+            level,
+            message,
+            fields,
+        })
+    }
+}
+
+impl Parse for Field {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = if input.peek(LitStr) {
+            input.parse::<LitStr>()?.value()
+        } else {
+            let segments = Punctuated::<Ident, Token![.]>::parse_separated_nonempty(input)
+                .map_err(|e| syn::Error::new(e.span(), "expected a field: `name = value`"))?;
+            segments
+                .iter()
+                .map(Ident::to_string)
+                .collect::<Vec<_>>()
+                .join(".")
+        };
+        input.parse::<Token![=]>()?;
+        let value = input.parse()?;
+        Ok(Self { name, value })
+    }
+}
+
+impl Node {
+    pub(crate) fn expand(&self) -> TokenStream2 {
+        let mut pattern = match &self.name {
+            Some(name) => quote!(::test_span::SpanPattern::new(#name)),
+            None => quote!(::test_span::SpanPattern::any()),
+        };
+        if self.ordered {
+            pattern = quote!(#pattern.ordered());
+        }
+        for Field { name, value } in &self.fields {
+            pattern = quote!(#pattern.field(#name, #value));
+        }
+        for item in &self.items {
+            pattern = match item {
+                Item::Node(node) => {
+                    let child = node.expand();
+                    if node.descendant.is_some() {
+                        quote!(#pattern.descendant(#child))
+                    } else {
+                        quote!(#pattern.child(#child))
+                    }
+                }
+                Item::Event(event) => {
+                    let event = event.expand();
+                    quote!(#pattern.event(#event))
+                }
+            };
+        }
+        pattern
+    }
+
+    pub(crate) fn check_root(&self) -> syn::Result<()> {
+        if let Some(descendant) = &self.descendant {
+            return Err(syn::Error::new_spanned(
+                descendant,
+                "the root of a span tree can't be a descendant (`..`), use `_ { .. \"name\" }` instead",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Event {
+    fn expand(&self) -> TokenStream2 {
+        let level = &self.level;
+        let mut pattern = quote!(::test_span::EventPattern::new().level(#level));
+        if let Some(message) = &self.message {
+            pattern = quote!(#pattern.message(#message));
+        }
+        for Field { name, value } in &self.fields {
+            pattern = quote!(#pattern.field(#name, #value));
+        }
+        pattern
+    }
+}
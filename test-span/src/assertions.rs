@@ -0,0 +1,557 @@
+//! Assertion macros over captured spans and logs.
+use crate::matcher::{descendants, name_matches, value_matches};
+use crate::text::{event_to_string, value_to_string};
+use crate::{EventPattern, LogEvent, RecordValue, Records, Span};
+use std::fmt::{self, Debug, Display};
+use std::ops::{Range, RangeFrom, RangeInclusive, RangeTo, RangeToInclusive};
+
+/// How many times something is expected to happen: an exact `usize`, or a range of `usize`.
+pub trait Count: Debug {
+    fn contains_count(&self, count: usize) -> bool;
+
+    /// How the count reads in an assertion failure, such as "at least once".
+    fn describe(&self) -> String {
+        format!("{self:?} times")
+    }
+}
+
+impl Count for usize {
+    fn contains_count(&self, count: usize) -> bool {
+        *self == count
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            0 => "never".to_string(),
+            1 => "exactly once".to_string(),
+            count => format!("exactly {count} times"),
+        }
+    }
+}
+
+impl Count for RangeFrom<usize> {
+    fn contains_count(&self, count: usize) -> bool {
+        self.contains(&count)
+    }
+
+    fn describe(&self) -> String {
+        match self.start {
+            1 => "at least once".to_string(),
+            start => format!("at least {start} times"),
+        }
+    }
+}
+
+macro_rules! impl_count_for_ranges {
+    ($($range:ty),*) => {
+        $(
+            impl Count for $range {
+                fn contains_count(&self, count: usize) -> bool {
+                    self.contains(&count)
+                }
+            }
+        )*
+    };
+}
+
+impl_count_for_ranges!(
+    Range<usize>,
+    RangeInclusive<usize>,
+    RangeTo<usize>,
+    RangeToInclusive<usize>
+);
+
+/// How many candidates are listed when no event matches.
+const NEAREST_CANDIDATES: usize = 3;
+
+impl Records {
+    /// The events matching `pattern`, in the order they were recorded.
+    pub fn matching<'a>(&'a self, pattern: &'a EventPattern) -> impl Iterator<Item = &'a LogEvent> {
+        self.events().filter(move |event| pattern.matches(event))
+    }
+}
+
+#[doc(hidden)]
+pub fn __check_log(
+    records: &Records,
+    pattern: &EventPattern,
+    times: &dyn Count,
+) -> Result<(), String> {
+    let matching: Vec<&LogEvent> = records.matching(pattern).collect();
+    if times.contains_count(matching.len()) {
+        return Ok(());
+    }
+
+    let mut message = format!(
+        "expected a log matching {pattern} {}, found {}",
+        times.describe(),
+        matching.len()
+    );
+    if matching.is_empty() {
+        let mut candidates: Vec<(&LogEvent, Vec<String>)> = records
+            .events()
+            .map(|event| (event, pattern.mismatches(event)))
+            .collect();
+        // sort_by_key is stable, so candidates with as many mismatches stay in the order they were recorded
+        candidates.sort_by_key(|(_, mismatches)| mismatches.len());
+        if candidates.is_empty() {
+            message.push_str("\nno logs were recorded");
+        } else {
+            message.push_str("\nnearest candidates:");
+        }
+        for (event, mismatches) in candidates.into_iter().take(NEAREST_CANDIDATES) {
+            message.push_str(&format!("\n  {}", describe(event)));
+            for mismatch in mismatches {
+                message.push_str(&format!("\n      {mismatch}"));
+            }
+        }
+    } else {
+        message.push(':');
+        for event in matching {
+            message.push_str(&format!("\n  {}", describe(event)));
+        }
+    }
+    Err(message)
+}
+
+fn describe(event: &LogEvent) -> String {
+    let scope: Vec<&str> = event.scope().collect();
+    if scope.is_empty() {
+        event_to_string(event, "")
+    } else {
+        format!("{} (in {})", event_to_string(event, ""), scope.join("/"))
+    }
+}
+
+/// Builds an `EventPattern` and an expected `Count` out of `assert_log!` arguments.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log_pattern {
+    ($pattern:expr, $times:expr ;) => {
+        ($pattern, $times)
+    };
+    ($pattern:expr, $times:expr ; level = $level:ident $(, $($rest:tt)*)?) => {
+        $crate::__log_pattern!(
+            $pattern.level($crate::reexports::tracing::Level::$level), $times ; $($($rest)*)?
+        )
+    };
+    ($pattern:expr, $times:expr ; level = $level:expr $(, $($rest:tt)*)?) => {
+        $crate::__log_pattern!($pattern.level($level), $times ; $($($rest)*)?)
+    };
+    ($pattern:expr, $times:expr ; target = $target:expr $(, $($rest:tt)*)?) => {
+        $crate::__log_pattern!($pattern.target($target), $times ; $($($rest)*)?)
+    };
+    ($pattern:expr, $times:expr ; in_span = $span:expr $(, $($rest:tt)*)?) => {
+        $crate::__log_pattern!($pattern.in_span($span), $times ; $($($rest)*)?)
+    };
+    ($pattern:expr, $times:expr ; message =~ $regex:expr $(, $($rest:tt)*)?) => {
+        $crate::__log_pattern!($pattern.message_matches($regex), $times ; $($($rest)*)?)
+    };
+    ($pattern:expr, $times:expr ; message = $message:expr $(, $($rest:tt)*)?) => {
+        $crate::__log_pattern!($pattern.message($message), $times ; $($($rest)*)?)
+    };
+    ($pattern:expr, $times:expr ; times = $count:expr $(, $($rest:tt)*)?) => {
+        $crate::__log_pattern!($pattern, &$count ; $($($rest)*)?)
+    };
+    ($pattern:expr, $times:expr ; $($field:ident).+ = $value:expr $(, $($rest:tt)*)?) => {
+        $crate::__log_pattern!(
+            $pattern.field(stringify!($($field).+).replace(' ', ""), $value), $times ; $($($rest)*)?
+        )
+    };
+}
+
+/// Asserts `logs` contain an event matching the given criteria.
+///
+/// Criteria are `level = WARN`, `target = "my_crate::db"` (which also matches submodules),
+/// `in_span = "query"` (the event was emitted in a span called `query`, or in one of its children),
+/// `message = "exact message"`, `message =~ r"regex"` and `field.name = value`.
+///
+/// `times = 2` (or a range such as `times = 1..=3`) checks how many events match.
+///
+/// ```ignore
+/// assert_log!(
+///     logs,
+///     level = WARN,
+///     target = "my_crate::db",
+///     in_span = "query",
+///     message =~ r"timed out after \d+ms",
+///     retries = 3,
+/// );
+/// ```
+///
+/// On failure, the panic message lists the events that were the closest to matching.
+#[macro_export]
+macro_rules! assert_log {
+    ($logs:expr, $($criteria:tt)+) => {
+        match $crate::__log_pattern!($crate::EventPattern::new(), &(1usize..) ; $($criteria)+) {
+            (pattern, times) => {
+                if let Err(error) = $crate::__check_log(&$logs, &pattern, times) {
+                    panic!("{}", error);
+                }
+            }
+        }
+    };
+}
+
+/// Asserts no event in `logs` matches the given criteria, which are the same as `assert_log!`'s.
+#[macro_export]
+macro_rules! assert_no_log {
+    // `times` goes first, so that the criteria can end with a comma
+    ($logs:expr, $($criteria:tt)+) => {
+        $crate::assert_log!($logs, times = 0usize, $($criteria)+)
+    };
+}
+
+/// Selects the spans `assert_span!` and friends count.
+#[derive(Debug)]
+pub struct SpanQuery {
+    name: String,
+    scope: Option<String>,
+    fields: Vec<(String, FieldPredicate)>,
+}
+
+enum FieldPredicate {
+    Equals(RecordValue),
+    Matches(Box<dyn Fn(&RecordValue) -> bool>),
+}
+
+impl Debug for FieldPredicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldPredicate::Equals(value) => f.debug_tuple("Equals").field(value).finish(),
+            FieldPredicate::Matches(_) => f.write_str("Matches(..)"),
+        }
+    }
+}
+
+impl SpanQuery {
+    /// Spans called `name`. Names match like they do in [`crate::SpanPattern::new`].
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            scope: None,
+            fields: Vec::new(),
+        }
+    }
+
+    /// Only count the spans under a span called `name`.
+    ///
+    /// If several spans are called `name`, the count is checked under each of them.
+    pub fn in_span(self, name: impl Into<String>) -> Self {
+        Self {
+            scope: Some(name.into()),
+            ..self
+        }
+    }
+
+    /// The span must have recorded `value` for `field`.
+    pub fn field(mut self, field: impl Into<String>, value: impl Into<serde_json::Value>) -> Self {
+        self.fields.push((
+            field.into(),
+            FieldPredicate::Equals(RecordValue::value(value)),
+        ));
+        self
+    }
+
+    /// The span must have recorded a value for `field` that satisfies `predicate`.
+    pub fn field_matches(
+        mut self,
+        field: impl Into<String>,
+        predicate: impl Fn(&RecordValue) -> bool + 'static,
+    ) -> Self {
+        self.fields
+            .push((field.into(), FieldPredicate::Matches(Box::new(predicate))));
+        self
+    }
+
+    pub fn matches(&self, span: &Span) -> bool {
+        name_matches(&self.name, span.name())
+            && self.fields.iter().all(|(field, predicate)| {
+                span.fields()
+                    .filter(|(name, _)| name == field)
+                    .any(|(_, value)| match predicate {
+                        FieldPredicate::Equals(expected) => value_matches(expected, value),
+                        FieldPredicate::Matches(predicate) => predicate(value),
+                    })
+            })
+    }
+
+    /// The regions the spans are counted in: `root` itself, or each span under it `in_span` selects.
+    fn regions<'a>(&self, root: &'a Span) -> Vec<(String, &'a Span)> {
+        match &self.scope {
+            None => vec![(root.name().to_string(), root)],
+            Some(scope) => descendants(root, root.name())
+                .into_iter()
+                .filter(|(_, span, _)| name_matches(scope, span.name()))
+                .map(|(path, span, _)| (path, span))
+                .collect(),
+        }
+    }
+
+    /// The spans matching the query under `region`, with their paths.
+    fn find<'a>(
+        &self,
+        path: &str,
+        region: &'a Span,
+        include_region: bool,
+    ) -> Vec<(String, &'a Span)> {
+        let mut spans = Vec::new();
+        if include_region {
+            spans.push((path.to_string(), region));
+        }
+        spans.extend(
+            descendants(region, path)
+                .into_iter()
+                .map(|(path, span, _)| (path, span)),
+        );
+        spans.retain(|(_, span)| self.matches(span));
+        spans
+    }
+}
+
+impl Display for SpanQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}", self.name)?;
+        for (field, predicate) in &self.fields {
+            match predicate {
+                FieldPredicate::Equals(value) => write!(f, " {field}={}", value_to_string(value))?,
+                FieldPredicate::Matches(_) => write!(f, " {field}=<predicate>")?,
+            }
+        }
+        f.write_str("`")?;
+        if let Some(scope) = &self.scope {
+            write!(f, " in each `{scope}`")?;
+        }
+        Ok(())
+    }
+}
+
+#[doc(hidden)]
+pub fn __check_spans(spans: &Span, query: &SpanQuery, times: &dyn Count) -> Result<(), String> {
+    let regions = query.regions(spans);
+    // without any `in_span` span, there is no matching span either
+    if regions.is_empty() && !times.contains_count(0) {
+        return Err(format!(
+            "expected a span matching {query} {}, but no span matches `{}`\n{}",
+            times.describe(),
+            query.scope.as_deref().unwrap_or_default(),
+            indent(&spans.to_string())
+        ));
+    }
+
+    for (path, region) in regions {
+        let matching = query.find(&path, region, query.scope.is_none());
+        if times.contains_count(matching.len()) {
+            continue;
+        }
+
+        let mut message = format!(
+            "expected a span matching {query} {}, found {}",
+            times.describe(),
+            matching.len()
+        );
+        if query.scope.is_some() {
+            message.push_str(&format!(" in {path}"));
+        }
+        for (path, _) in matching {
+            message.push_str(&format!("\n  {path}"));
+        }
+        message.push_str(&format!("\n{}", indent(&region.to_string())));
+        return Err(message);
+    }
+    Ok(())
+}
+
+fn indent(text: &str) -> String {
+    text.lines()
+        .map(|line| format!("    {line}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Builds a `SpanQuery` and an expected `Count` out of `assert_span!` arguments.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __span_query {
+    ($query:expr, $times:expr ;) => {
+        ($query, $times)
+    };
+    ($query:expr, $times:expr ; in_span = $span:expr $(, $($rest:tt)*)?) => {
+        $crate::__span_query!($query.in_span($span), $times ; $($($rest)*)?)
+    };
+    ($query:expr, $times:expr ; times = $count:expr $(, $($rest:tt)*)?) => {
+        $crate::__span_query!($query, &$count ; $($($rest)*)?)
+    };
+    ($query:expr, $times:expr ; $($field:ident).+ => $predicate:expr $(, $($rest:tt)*)?) => {
+        $crate::__span_query!(
+            $query.field_matches(stringify!($($field).+).replace(' ', ""), $predicate), $times ; $($($rest)*)?
+        )
+    };
+    ($query:expr, $times:expr ; $($field:ident).+ = $value:expr $(, $($rest:tt)*)?) => {
+        $crate::__span_query!(
+            $query.field(stringify!($($field).+).replace(' ', ""), $value), $times ; $($($rest)*)?
+        )
+    };
+}
+
+/// Asserts `spans` contain a span called `name`.
+///
+/// Criteria are `field.name = value`, `field.name => |value| predicate`,
+/// and `in_span = "request"`, which checks the spans under each span called `request`
+/// instead of the whole tree.
+///
+/// `times = 2` (or a range such as `times = 1..=3`) checks how many spans match.
+///
+/// ```ignore
+/// assert_span!(spans, "db::query", in_span = "request", times = 1);
+/// assert_span!(spans, "http::request", status = 200, method => |value| value != &RecordValue::Value("GET".into()));
+/// ```
+///
+/// On failure, the panic message renders the region of the tree the spans were looked for in.
+#[macro_export]
+macro_rules! assert_span {
+    ($spans:expr, $name:expr $(, $($criteria:tt)*)?) => {
+        match $crate::__span_query!($crate::SpanQuery::new($name), &(1usize..) ; $($($criteria)*)?) {
+            (query, times) => {
+                if let Err(error) = $crate::__check_spans(&$spans, &query, times) {
+                    panic!("{}", error);
+                }
+            }
+        }
+    };
+}
+
+/// Asserts no span in `spans` is called `name`, with the same criteria as `assert_span!`.
+#[macro_export]
+macro_rules! assert_no_span {
+    ($spans:expr, $name:expr $(, $($criteria:tt)*)?) => {
+        $crate::assert_span!($spans, $name, times = 0usize $(, $($criteria)*)?)
+    };
+}
+
+/// Asserts how many spans in `spans` are called `name`, with the same criteria as `assert_span!`.
+///
+/// ```ignore
+/// assert_span_count!(spans, "cache::miss", ..=2);
+/// ```
+#[macro_export]
+macro_rules! assert_span_count {
+    ($spans:expr, $name:expr, $count:expr $(, $($criteria:tt)*)?) => {
+        $crate::assert_span!($spans, $name, times = $count $(, $($criteria)*)?)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TREE: &str = r#"tests::root (INFO)
+  - INFO "here i am!" number=52
+  my_crate::db::query (INFO)
+    - WARN "timed out after 30ms" retries=3
+    - WARN "timed out after 60ms" retries=2
+  my_crate::http::request (INFO)
+    - ERROR "gave up"
+"#;
+
+    fn logs() -> Records {
+        fn collect(span: &crate::Span, events: &mut Vec<LogEvent>) {
+            events.extend(span.events().cloned());
+            span.children().for_each(|child| collect(child, events));
+        }
+        let mut events = Vec::new();
+        collect(&TREE.parse().unwrap(), &mut events);
+        Records::default().with_events(events)
+    }
+
+    #[test]
+    fn assert_log_matches_criteria() {
+        let logs = logs();
+        assert_log!(logs, level = WARN, target = "my_crate", in_span = "query", message =~ r"after \d+ms", retries = 3);
+        assert_log!(logs, message =~ r"^timed out", times = 2);
+        assert_log!(logs, level = tracing::Level::ERROR, times = 1..=3);
+        assert_no_log!(logs, level = WARN, retries = 4);
+        assert_no_log!(logs, level = DEBUG);
+        assert_no_log!(logs, level = ERROR, in_span = "query");
+        assert_no_log!(logs, level = DEBUG,);
+    }
+
+    #[test]
+    fn failures_list_the_nearest_candidates() {
+        let logs = logs();
+        let pattern = EventPattern::new()
+            .level(tracing::Level::WARN)
+            .message_matches(r"timed out after \d+ms")
+            .field("retries", 4);
+        assert_eq!(
+            __check_log(&logs, &pattern, &(1usize..)).unwrap_err(),
+            r#"expected a log matching `WARN =~ /timed out after \d+ms/ retries=4` at least once, found 0
+nearest candidates:
+  WARN my_crate::db: "timed out after 30ms" retries=3 (in tests::root/my_crate::db::query)
+      retries is 3, expected 4
+  WARN my_crate::db: "timed out after 60ms" retries=2 (in tests::root/my_crate::db::query)
+      retries is 2, expected 4
+  INFO tests: "here i am!" number=52 (in tests::root)
+      level is INFO, expected WARN
+      message is "here i am!", expected =~ /timed out after \d+ms/
+      retries wasn't recorded, expected 4"#
+        );
+
+        let pattern = EventPattern::new().level(tracing::Level::WARN);
+        assert_eq!(
+            __check_log(&logs, &pattern, &0usize).unwrap_err(),
+            r#"expected a log matching `WARN` never, found 2:
+  WARN my_crate::db: "timed out after 30ms" retries=3 (in tests::root/my_crate::db::query)
+  WARN my_crate::db: "timed out after 60ms" retries=2 (in tests::root/my_crate::db::query)"#
+        );
+    }
+
+    const REQUESTS: &str = r#"tests::root (INFO)
+  http::request (INFO) method="GET"
+    db::query (INFO) table="users"
+    cache::miss (INFO)
+  http::request (INFO) method="POST"
+    db::query (INFO) table="users"
+    db::query (INFO) table="orders"
+"#;
+
+    #[test]
+    fn span_assertions_count_matching_spans() {
+        let spans: Span = REQUESTS.parse().unwrap();
+        assert_span!(spans, "root");
+        assert_span!(spans, "http::request", method = "POST");
+        assert_span_count!(spans, "db::query", 3);
+        assert_span_count!(spans, "db::query", 1..=2, in_span = "request");
+        assert_span_count!(
+            spans,
+            "query",
+            1,
+            table = "users",
+            in_span = "http::request"
+        );
+        assert_span!(spans, "request", method => |value| value != &RecordValue::Value("GET".into()));
+        assert_no_span!(spans, "cache::miss", in_span = "db::query");
+        assert_no_span!(spans, "db::query", in_span = "not::there");
+        assert_no_span!(spans, "db::query", table = "products");
+    }
+
+    #[test]
+    fn span_failures_render_the_region() {
+        let spans: Span = REQUESTS.parse().unwrap();
+        let query = SpanQuery::new("db::query").in_span("http::request");
+        assert_eq!(
+            __check_spans(&spans, &query, &1usize).unwrap_err(),
+            r#"expected a span matching `db::query` in each `http::request` exactly once, found 2 in tests::root/http::request[1]
+  tests::root/http::request[1]/db::query[0]
+  tests::root/http::request[1]/db::query[1]
+    http::request (INFO) method="POST"
+      db::query (INFO) table="users"
+      db::query (INFO) table="orders""#
+        );
+
+        let query = SpanQuery::new("db::query").in_span("grpc::request");
+        assert!(__check_spans(&spans, &query, &(1usize..))
+            .unwrap_err()
+            .starts_with("expected a span matching `db::query` in each `grpc::request` at least once, but no span matches `grpc::request`"));
+    }
+}
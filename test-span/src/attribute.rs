@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
 use tracing::{Level, Metadata};
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, Hash)]
@@ -7,6 +8,17 @@ pub struct OwnedFieldSet {
     names: Vec<String>,
 }
 
+impl OwnedFieldSet {
+    pub fn new(names: Vec<String>) -> Self {
+        Self { names }
+    }
+
+    /// The field names, in declaration order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.names.iter().map(String::as_str)
+    }
+}
+
 impl From<&tracing::field::FieldSet> for OwnedFieldSet {
     fn from(fs: &tracing::field::FieldSet) -> Self {
         Self {
@@ -76,4 +88,30 @@ impl OwnedMetadata {
     pub fn is_enabled(&self, max_verbosity_level: &Level) -> bool {
         &self.level.parse::<tracing::Level>().unwrap_or(Level::INFO) <= max_verbosity_level
     }
+
+    /// Where the span or event was created.
+    pub fn location(&self) -> Location {
+        Location {
+            file: self.file.clone(),
+            line: self.line,
+        }
+    }
+}
+
+/// Where a span or event was created, as far as its metadata knows.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Location {
+    pub file: Option<String>,
+    pub line: Option<u32>,
+}
+
+/// `at file:line`, `in file` or `at an unknown location`
+impl Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, "at {file}:{line}"),
+            (Some(file), None) => write!(f, "in {file}"),
+            _ => write!(f, "at an unknown location"),
+        }
+    }
 }
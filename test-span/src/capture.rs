@@ -0,0 +1,68 @@
+//! Capturing telemetry without `#[test_span]`, for tests generated by other macros or helpers.
+use crate::current::{__RegisteredTest, __register_test};
+use crate::{Filter, TestTelemetry};
+use std::future::Future;
+use tracing::span::EnteredSpan;
+use tracing::{Instrument, Level};
+
+/// Runs `f` in a new root span, and returns its result along with what it recorded.
+///
+/// The telemetry is filtered at the `INFO` level, `with_filter` looks at it at other levels.
+pub fn capture<T>(f: impl FnOnce() -> T) -> (T, TestTelemetry) {
+    let (root_span, telemetry, _registered) = root();
+    (root_span.in_scope(f), telemetry)
+}
+
+/// Runs `future` in a new root span, and returns its output along with what it recorded.
+pub async fn capture_async<T>(future: impl Future<Output = T>) -> (T, TestTelemetry) {
+    let (root_span, telemetry, _registered) = root();
+    (future.instrument(root_span).await, telemetry)
+}
+
+/// Enters a new root span until the returned `Session` is finished.
+///
+/// Unlike `capture`, everything between `start` and `finish` is recorded,
+/// which suits tests that go through several helper calls.
+/// The root span stays entered on the current thread, so sessions are for synchronous code:
+/// use `capture_async` for futures.
+#[must_use = "the session records until it is finished"]
+pub fn start() -> Session {
+    let (root_span, telemetry, registered) = root();
+    Session {
+        telemetry,
+        _entered: root_span.entered(),
+        _registered: registered,
+    }
+}
+
+/// A root span that stays entered, see `start`.
+pub struct Session {
+    telemetry: TestTelemetry,
+    _entered: EnteredSpan,
+    _registered: __RegisteredTest,
+}
+
+impl Session {
+    /// What the session recorded so far.
+    pub fn telemetry(&self) -> &TestTelemetry {
+        &self.telemetry
+    }
+
+    /// Exits and closes the root span, and returns what the session recorded.
+    pub fn finish(self) -> TestTelemetry {
+        self.telemetry
+    }
+}
+
+fn root() -> (tracing::Span, TestTelemetry, __RegisteredTest) {
+    crate::init();
+
+    let root_span = tracing::span!(target: "test_span", parent: None, Level::INFO, "capture");
+    let root_id = root_span
+        .id()
+        .expect("couldn't get root span id; this cannot happen.");
+    let telemetry = TestTelemetry::new(root_id, Filter::new(Level::INFO));
+    let registered = __register_test(&telemetry);
+
+    (root_span, telemetry, registered)
+}
@@ -0,0 +1,110 @@
+//! Marks in the run of a test, taken from the global sequence numbers,
+//! and the views of a `Report` between them.
+use crate::layer::next_sequence;
+use crate::Report;
+use daggy::{Dag, NodeIndex, Walker};
+use indexmap::IndexMap;
+
+/// A point of the run: everything recorded afterwards has a greater sequence number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Checkpoint(u64);
+
+impl Checkpoint {
+    pub fn now() -> Self {
+        Self(next_sequence())
+    }
+
+    pub fn sequence(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Marks the current point of the run, see `Report::since` and `Report::between`.
+pub fn checkpoint() -> Checkpoint {
+    Checkpoint::now()
+}
+
+impl Report {
+    /// The spans opened and the events emitted after `mark`.
+    ///
+    /// See `between`.
+    pub fn since(&self, mark: Checkpoint) -> Report {
+        self.window(mark.0, u64::MAX)
+    }
+
+    /// The spans opened and the events emitted after `start` and before `end`.
+    ///
+    /// The root is always kept. The children of spans opened outside of the window
+    /// are attached to their closest ancestor in it, and so are the events emitted in them,
+    /// the same way spans that are filtered out by level are skipped.
+    pub fn between(&self, start: Checkpoint, end: Checkpoint) -> Report {
+        self.window(start.0, end.0)
+    }
+
+    pub(crate) fn window(&self, after: u64, before: u64) -> Report {
+        let mut dag: Dag<u64, ()> = Dag::new();
+        let mut node_to_id = IndexMap::new();
+        // where each node of the original dag ends up: itself, its closest kept ancestor, or nowhere
+        let mut moved_to: IndexMap<NodeIndex, NodeIndex> = IndexMap::new();
+        let mut root_index = None;
+
+        // parents are added to the dag before their children, so they come first
+        let mut nodes: Vec<_> = self.node_to_id.iter().collect();
+        nodes.sort();
+        for (&node, &id) in nodes {
+            let parent = self
+                .dag
+                .parents(node)
+                .iter(&self.dag)
+                .next()
+                .and_then(|(_, parent)| moved_to.get(&parent).copied());
+            let opened = self
+                .spans
+                .get(&id)
+                .and_then(|recorder| recorder.opened_sequence())
+                .is_some_and(|sequence| after < sequence && sequence < before);
+
+            let new_node = if id == self.root_id {
+                let root = dag.add_node(id);
+                root_index = Some(root);
+                root
+            } else {
+                match parent {
+                    Some(parent) if opened => dag.add_child(parent, (), id).1,
+                    Some(parent) => {
+                        moved_to.insert(node, parent);
+                        continue;
+                    }
+                    None => continue,
+                }
+            };
+            moved_to.insert(node, new_node);
+            node_to_id.insert(new_node, id);
+        }
+
+        let id_to_node: IndexMap<u64, NodeIndex> = self
+            .node_to_id
+            .iter()
+            .map(|(node, id)| (*id, *node))
+            .collect();
+        let logs = self.logs.window(after, before, |span_id| {
+            let node = moved_to.get(id_to_node.get(&span_id)?)?;
+            node_to_id.get(node).copied()
+        });
+        let spans = self
+            .spans
+            .iter()
+            .filter(|(id, _)| node_to_id.values().any(|kept| kept == *id))
+            .map(|(id, recorder)| (*id, recorder.clone()))
+            .collect();
+
+        Report {
+            root_index: root_index.expect("the root is always in the window; qed"),
+            root_id: self.root_id,
+            dag,
+            spans,
+            logs,
+            node_to_id,
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! Whether spans were active at the same time.
+//!
+//! A span is active from the first time it is entered to the last time it is exited.
+//! An async span that is polled, yields, then is polled again stays active in between,
+//! so two spans overlap if one of them was entered while the other one was still pending.
+use crate::matcher::name_matches;
+use crate::report::span_name;
+use crate::Report;
+use std::fmt::{self, Display};
+use std::time::SystemTime;
+
+/// When a span was active, in sequence numbers and in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActiveInterval {
+    pub start: u64,
+    pub started_at: SystemTime,
+    /// `None` if the span is still entered
+    pub end: Option<u64>,
+    pub ended_at: Option<SystemTime>,
+}
+
+impl ActiveInterval {
+    pub fn overlaps(&self, other: &ActiveInterval) -> bool {
+        self.start < other.end.unwrap_or(u64::MAX) && other.start < self.end.unwrap_or(u64::MAX)
+    }
+}
+
+impl Display for ActiveInterval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.end {
+            Some(end) => write!(f, "#{}..#{end}", self.start),
+            None => write!(f, "#{}..", self.start),
+        }
+    }
+}
+
+impl Report {
+    /// When the span `span_id` was active, `None` if it was never entered.
+    pub fn active_interval(&self, span_id: u64) -> Option<ActiveInterval> {
+        let recorder = self.spans.get(&span_id)?;
+        let (start, started_at) = recorder.first_entered()?;
+        let ended = recorder.last_exited();
+        Some(ActiveInterval {
+            start,
+            started_at,
+            end: ended.map(|(end, _)| end),
+            ended_at: ended.map(|(_, ended_at)| ended_at),
+        })
+    }
+
+    /// Whether the spans `a` and `b` were active at the same time,
+    /// `None` if one of them was never entered.
+    pub fn overlapped(&self, a: u64, b: u64) -> Option<bool> {
+        Some(self.active_interval(a)?.overlaps(&self.active_interval(b)?))
+    }
+
+    // The ids of the spans under the root called `name`, in creation order.
+    fn span_ids<'a>(&'a self, name: &'a str) -> impl Iterator<Item = u64> + 'a {
+        self.spans_under_root()
+            .filter(move |(_, recorder)| {
+                recorder
+                    .metadata()
+                    .is_some_and(|metadata| name_matches(name, &span_name(metadata)))
+            })
+            .map(|(id, _)| *id)
+    }
+}
+
+#[doc(hidden)]
+pub fn __check_overlap(
+    report: &Report,
+    a: &str,
+    b: Option<&str>,
+    concurrent: bool,
+) -> Result<(), String> {
+    let expected = if concurrent {
+        "to run concurrently"
+    } else {
+        "to run sequentially"
+    };
+    let description = match b {
+        Some(b) => format!("`{a}` and `{b}` spans"),
+        None => format!("`{a}` spans"),
+    };
+
+    let intervals = |name: &str| {
+        report
+            .span_ids(name)
+            .map(|id| {
+                report
+                    .active_interval(id)
+                    .map(|interval| (id, interval))
+                    .ok_or_else(|| {
+                        format!("expected {description} {expected}, but `{name}` #{id} was never entered")
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()
+    };
+    let left = intervals(a)?;
+    let right = match b {
+        Some(b) => intervals(b)?,
+        None => left.clone(),
+    };
+    // with a single name, each pair of distinct spans is compared once
+    let pairs: Vec<_> = left
+        .iter()
+        .flat_map(|l| right.iter().map(move |r| (l, r)))
+        .filter(|(l, r)| if b.is_some() { l.0 != r.0 } else { l.0 < r.0 })
+        .collect();
+    if pairs.is_empty() {
+        return Err(format!(
+            "expected {description} {expected}, but there aren't two spans to compare"
+        ));
+    }
+
+    let b = b.unwrap_or(a);
+    for ((left_id, left), (right_id, right)) in pairs {
+        if left.overlaps(right) != concurrent {
+            let relation = if concurrent {
+                "didn't overlap"
+            } else {
+                "overlapped"
+            };
+            return Err(format!(
+                "expected {description} {expected}, but `{a}` #{left_id} ({left}) and `{b}` #{right_id} ({right}) {relation}"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Asserts the spans called `name` were all active at the same time,
+/// or that each span called `a` was active at the same time as each span called `b`.
+///
+/// ```ignore
+/// // catches a `for` loop with `.await` that replaced `join_all`
+/// assert_concurrent!(get_report(), "subgraph::fetch");
+/// assert_concurrent!(get_report(), "cache::refresh", "upstream::fetch");
+/// ```
+#[macro_export]
+macro_rules! assert_concurrent {
+    ($report:expr, $name:expr $(,)?) => {
+        if let Err(error) = $crate::__check_overlap(&$report, $name, None, true) {
+            panic!("{}", error);
+        }
+    };
+    ($report:expr, $a:expr, $b:expr $(,)?) => {
+        if let Err(error) = $crate::__check_overlap(&$report, $a, Some($b), true) {
+            panic!("{}", error);
+        }
+    };
+}
+
+/// Asserts no two spans called `name` were active at the same time,
+/// or that no span called `a` was active at the same time as a span called `b`.
+#[macro_export]
+macro_rules! assert_sequential {
+    ($report:expr, $name:expr $(,)?) => {
+        if let Err(error) = $crate::__check_overlap(&$report, $name, None, false) {
+            panic!("{}", error);
+        }
+    };
+    ($report:expr, $a:expr, $b:expr $(,)?) => {
+        if let Err(error) = $crate::__check_overlap(&$report, $a, Some($b), false) {
+            panic!("{}", error);
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interval(start: u64, end: Option<u64>) -> ActiveInterval {
+        ActiveInterval {
+            start,
+            started_at: SystemTime::UNIX_EPOCH,
+            end,
+            ended_at: None,
+        }
+    }
+
+    #[test]
+    fn intervals_overlap() {
+        assert!(interval(1, Some(4)).overlaps(&interval(2, Some(3))));
+        assert!(interval(1, Some(4)).overlaps(&interval(3, Some(6))));
+        assert!(interval(3, Some(6)).overlaps(&interval(1, Some(4))));
+        assert!(interval(1, None).overlaps(&interval(5, Some(6))));
+        assert!(!interval(1, Some(2)).overlaps(&interval(3, Some(4))));
+        assert!(!interval(3, Some(4)).overlaps(&interval(1, Some(2))));
+        assert_eq!("#1..#2", interval(1, Some(2)).to_string());
+        assert_eq!("#1..", interval(1, None).to_string());
+    }
+}
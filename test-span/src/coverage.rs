@@ -0,0 +1,363 @@
+//! Which span and event callsites the tests went through.
+//!
+//! A span or event only counts if the levels of the test it happened in let it through,
+//! so callsites that only ever recorded what the tests filter out are reported as unexercised.
+use crate::current::test_filter;
+use crate::{Layer, LazyMutex, Location, OwnedMetadata};
+use indexmap::IndexMap;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::callsite::Identifier;
+use tracing::Metadata;
+
+/// The environment variable that sets where the coverage report is merged into once each `#[test_span]` test is over.
+pub const COVERAGE_DIR: &str = "TEST_SPAN_COVERAGE_DIR";
+
+static CALLSITES: LazyMutex<Inventory> = Lazy::new(Default::default);
+
+/// Whether a callsite creates spans or emits events
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CallsiteKind {
+    Span,
+    Event,
+}
+
+/// A `span!` or `event!` callsite `tracing` registered, and how many times it was hit.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Callsite {
+    pub kind: CallsiteKind,
+    pub target: String,
+    pub name: String,
+    pub level: String,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    /// How many spans it created or events it emitted, that the test they happened in recorded
+    pub hits: u64,
+    /// How many spans or events the levels of the test they happened in filtered out
+    #[serde(default)]
+    pub filtered: u64,
+}
+
+impl Callsite {
+    fn new(metadata: &Metadata<'_>) -> Self {
+        Self {
+            kind: if metadata.is_span() {
+                CallsiteKind::Span
+            } else {
+                CallsiteKind::Event
+            },
+            target: metadata.target().to_string(),
+            name: metadata.name().to_string(),
+            level: metadata.level().to_string(),
+            file: metadata.file().map(str::to_string),
+            line: metadata.line(),
+            hits: 0,
+            filtered: 0,
+        }
+    }
+
+    fn is(&self, other: &Callsite) -> bool {
+        (self.kind, &self.target, &self.name, &self.file, self.line)
+            == (
+                other.kind,
+                &other.target,
+                &other.name,
+                &other.file,
+                other.line,
+            )
+    }
+}
+
+impl Display for Callsite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // event names are made of their location, which comes next anyway
+        match self.kind {
+            CallsiteKind::Span => write!(f, "span  {}::{}", self.target, self.name)?,
+            CallsiteKind::Event => write!(f, "event {}", self.target)?,
+        }
+        let location = Location {
+            file: self.file.clone(),
+            line: self.line,
+        };
+        write!(f, " ({}) {location}", self.level)
+    }
+}
+
+/// The callsites the layer heard about, in the order they were registered.
+#[derive(Default)]
+pub(crate) struct Inventory(IndexMap<Identifier, Callsite>);
+
+impl Inventory {
+    pub(crate) fn register(&mut self, metadata: &'static Metadata<'static>) {
+        self.0
+            .entry(metadata.callsite())
+            .or_insert_with(|| Callsite::new(metadata));
+    }
+
+    pub(crate) fn hit(&mut self, metadata: &'static Metadata<'static>, enabled: bool) {
+        let callsite = self
+            .0
+            .entry(metadata.callsite())
+            .or_insert_with(|| Callsite::new(metadata));
+        if enabled {
+            callsite.hits += 1;
+        } else {
+            callsite.filtered += 1;
+        }
+    }
+
+    fn coverage(&self) -> Coverage {
+        Coverage {
+            callsites: self.0.values().cloned().collect(),
+        }
+    }
+}
+
+pub(crate) fn register(metadata: &'static Metadata<'static>) {
+    CALLSITES.lock().unwrap().register(metadata);
+}
+
+/// Counts a span or event, created or emitted in the span `within`.
+///
+/// Outside of tests, nothing filters it out.
+pub(crate) fn hit(metadata: &'static Metadata<'static>, within: Option<u64>) {
+    let enabled = within
+        .and_then(test_filter)
+        .is_none_or(|filter| filter.is_enabled(&OwnedMetadata::from(metadata)));
+    CALLSITES.lock().unwrap().hit(metadata, enabled);
+}
+
+/// Every callsite registered so far, see `Layer::coverage`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Coverage {
+    pub callsites: Vec<Callsite>,
+}
+
+impl Layer {
+    /// The span and event callsites registered so far in the process, and how many times each was hit.
+    ///
+    /// `tracing` registers a callsite the first time the code reaches it,
+    /// so callsites the run never got to aren't part of it.
+    /// Those that only ever produced what the tests' levels filter out are, with no hits.
+    pub fn coverage() -> Coverage {
+        CALLSITES.lock().unwrap().coverage()
+    }
+}
+
+impl Coverage {
+    /// The callsites that never created a span or emitted an event a test recorded, by location.
+    pub fn unexercised(&self) -> Vec<&Callsite> {
+        let mut unexercised: Vec<_> = self
+            .callsites
+            .iter()
+            .filter(|callsite| callsite.hits == 0)
+            .collect();
+        unexercised.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+        unexercised
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("callsites are plain data; qed")
+    }
+
+    /// How many callsites were exercised, followed by the ones that weren't.
+    pub fn summary(&self) -> String {
+        let unexercised = self.unexercised();
+        let total = self.callsites.len();
+        let exercised = total - unexercised.len();
+        let ratio = if total == 0 {
+            100.0
+        } else {
+            exercised as f64 * 100.0 / total as f64
+        };
+        let mut summary =
+            format!("{exercised} of {total} callsites were exercised ({ratio:.1}%)\n");
+        if !unexercised.is_empty() {
+            summary.push_str("never exercised:\n");
+            for callsite in unexercised {
+                summary.push_str(&format!("  {callsite}"));
+                if callsite.filtered > 0 {
+                    summary.push_str(&format!(
+                        ", filtered out {} time(s) by the tests' levels",
+                        callsite.filtered
+                    ));
+                }
+                summary.push('\n');
+            }
+        }
+        summary
+    }
+
+    /// Adds the callsites of `other`, keeping the highest counts of those both have.
+    pub fn merge(&mut self, other: Coverage) {
+        for callsite in other.callsites {
+            match self.callsites.iter_mut().find(|known| known.is(&callsite)) {
+                Some(known) => {
+                    known.hits = known.hits.max(callsite.hits);
+                    known.filtered = known.filtered.max(callsite.filtered);
+                }
+                None => self.callsites.push(callsite),
+            }
+        }
+    }
+
+    /// Writes `<test binary>.coverage.json` and `<test binary>.coverage.txt` to `dir`, and returns their paths.
+    ///
+    /// Each file is written next to its destination, then moved in place, so readers never see half of it.
+    pub fn write(&self, dir: &Path) -> std::io::Result<(PathBuf, PathBuf)> {
+        let (json, summary) = report_paths(dir)?;
+        std::fs::create_dir_all(dir)?;
+        write_whole(&json, &self.to_json())?;
+        write_whole(&summary, &self.summary())?;
+        Ok((json, summary))
+    }
+}
+
+// one report per test binary, so that the integration tests don't overwrite the unit tests'
+fn report_paths(dir: &Path) -> std::io::Result<(PathBuf, PathBuf)> {
+    let binary = std::env::current_exe()?
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "tests".to_string());
+    Ok((
+        dir.join(format!("{binary}.coverage.json")),
+        dir.join(format!("{binary}.coverage.txt")),
+    ))
+}
+
+fn write_whole(path: &Path, contents: &str) -> std::io::Result<()> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(format!(".{}.tmp", std::process::id()));
+    std::fs::write(&partial, contents)?;
+    std::fs::rename(&partial, path)
+}
+
+impl Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.summary())
+    }
+}
+
+/// Merges the coverage so far into the report in `$TEST_SPAN_COVERAGE_DIR` when dropped, if the variable is set.
+///
+/// `#[test_span]` creates one for each test, so once the last test is over the report covers the whole run,
+/// and merging keeps what runs of the same test binary in other processes found, when tests get a process each.
+#[doc(hidden)]
+pub struct __CoverageReport;
+
+impl Drop for __CoverageReport {
+    fn drop(&mut self) {
+        static WRITING: Mutex<()> = Mutex::new(());
+
+        let Some(dir) = std::env::var_os(COVERAGE_DIR) else {
+            return;
+        };
+        let _writing = WRITING
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let dir = Path::new(&dir);
+        let mut coverage = Layer::coverage();
+        let previous = report_paths(dir)
+            .and_then(|(json, _)| std::fs::read_to_string(json))
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok());
+        if let Some(previous) = previous {
+            coverage.merge(previous);
+        }
+        // errors are reported rather than raised, a panic while unwinding would abort the test run
+        if let Err(e) = coverage.write(dir) {
+            eprintln!(
+                "test-span: couldn't write the coverage report to {}: {e}",
+                dir.display()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing::callsite::Callsite as _;
+    use tracing::{Level, Metadata};
+
+    struct TestCallsite(&'static Metadata<'static>);
+
+    impl tracing::callsite::Callsite for TestCallsite {
+        fn set_interest(&self, _: tracing::subscriber::Interest) {}
+        fn metadata(&self) -> &Metadata<'_> {
+            self.0
+        }
+    }
+
+    macro_rules! callsite {
+        ($name:ident, $kind:expr, $line:expr) => {
+            static $name: TestCallsite = TestCallsite(&Metadata::new(
+                stringify!($name),
+                "my_crate::db",
+                Level::DEBUG,
+                Some("src/db.rs"),
+                Some($line),
+                None,
+                tracing::field::FieldSet::new(&[], tracing::callsite::Identifier(&$name)),
+                $kind,
+            ));
+        };
+    }
+
+    callsite!(QUERY, tracing::metadata::Kind::SPAN, 42);
+    callsite!(RETRY, tracing::metadata::Kind::EVENT, 12);
+    callsite!(CONNECT, tracing::metadata::Kind::SPAN, 3);
+
+    #[test]
+    fn unexercised_callsites_are_summarized() {
+        let mut inventory = Inventory::default();
+        inventory.register(QUERY.metadata());
+        inventory.register(RETRY.metadata());
+        inventory.hit(CONNECT.metadata(), true);
+        inventory.hit(CONNECT.metadata(), true);
+        inventory.hit(RETRY.metadata(), false);
+
+        let coverage = inventory.coverage();
+        assert_eq!(3, coverage.callsites.len());
+        assert_eq!(2, coverage.callsites[2].hits);
+        assert_eq!(
+            "1 of 3 callsites were exercised (33.3%)\n\
+            never exercised:\n  \
+            event my_crate::db (DEBUG) at src/db.rs:12, filtered out 1 time(s) by the tests' levels\n  \
+            span  my_crate::db::QUERY (DEBUG) at src/db.rs:42\n",
+            coverage.summary()
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&coverage.to_json()).unwrap();
+        assert_eq!("event", json["callsites"][1]["kind"]);
+        assert_eq!(1, json["callsites"][1]["filtered"]);
+        assert_eq!(42, json["callsites"][0]["line"]);
+    }
+
+    #[test]
+    fn coverages_are_merged() {
+        let mut first = Inventory::default();
+        first.hit(QUERY.metadata(), true);
+        first.register(RETRY.metadata());
+        let mut second = Inventory::default();
+        second.register(QUERY.metadata());
+        second.hit(RETRY.metadata(), true);
+        second.hit(RETRY.metadata(), true);
+        second.register(CONNECT.metadata());
+
+        let mut coverage = first.coverage();
+        let previous = serde_json::from_str(&second.coverage().to_json()).unwrap();
+        coverage.merge(previous);
+
+        let hits: Vec<_> = coverage
+            .callsites
+            .iter()
+            .map(|callsite| (callsite.name.as_str(), callsite.hits))
+            .collect();
+        assert_eq!(vec![("QUERY", 1), ("RETRY", 2), ("CONNECT", 0)], hits);
+    }
+}
@@ -0,0 +1,102 @@
+//! Telemetry accessors that find the running `#[test_span]` test through the current span.
+//!
+//! `#[test_span]` hands `telemetry` and `get_logs` and friends to the test body, but parameterized tests
+//! generated by `rstest` or `test_case` can keep the body in a function the macro never sees.
+//! These functions are what the body then resolves to, through the prelude.
+use crate::layer::SPAN_ID_TO_ROOT_AND_NODE_INDEX;
+use crate::{Filter, LazyMutex, Records, Report, Span, TestTelemetry};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+
+static TESTS: LazyMutex<HashMap<u64, TestTelemetry>> = Lazy::new(Default::default);
+
+#[doc(hidden)]
+/// Remembers the telemetry of the test, so it can be found from its spans, until the guard is dropped.
+pub fn __register_test(telemetry: &TestTelemetry) -> __RegisteredTest {
+    let root = telemetry.root_id().into_u64();
+    TESTS.lock().unwrap().insert(root, telemetry.clone());
+    __RegisteredTest(root)
+}
+
+#[doc(hidden)]
+/// Forgets the test when it ends, so finished tests don't pile up.
+pub struct __RegisteredTest(u64);
+
+impl Drop for __RegisteredTest {
+    fn drop(&mut self) {
+        TESTS.lock().unwrap().remove(&self.0);
+    }
+}
+
+/// The levels of the test the span `id` belongs to, `None` if it doesn't belong to one.
+pub(crate) fn test_filter(id: u64) -> Option<Filter> {
+    let root = SPAN_ID_TO_ROOT_AND_NODE_INDEX
+        .lock()
+        .unwrap()
+        .get(&id)
+        .map(|(root, _)| *root)?;
+    TESTS
+        .lock()
+        .unwrap()
+        .get(&root)
+        .map(|telemetry| telemetry.filter().clone())
+}
+
+#[track_caller]
+pub(crate) fn current_test() -> TestTelemetry {
+    let current = tracing::Span::current()
+        .id()
+        .map(|id| id.into_u64())
+        .and_then(|id| {
+            SPAN_ID_TO_ROOT_AND_NODE_INDEX
+                .lock()
+                .unwrap()
+                .get(&id)
+                .map(|(root, _)| *root)
+        })
+        .and_then(|root| TESTS.lock().unwrap().get(&root).cloned());
+    current.expect(
+        "no #[test_span] test is running in the current span, spawned tasks and threads need to be instrumented with it",
+    )
+}
+
+/// Returns both the output of `get_spans` and `get_logs`, for the test that is running.
+#[track_caller]
+pub fn get_telemetry() -> (Span, Records) {
+    current_test().telemetry()
+}
+
+/// Returns the span tree of the test that is running, filtered by its levels.
+#[track_caller]
+pub fn get_spans() -> Span {
+    current_test().spans()
+}
+
+/// Returns the logs of the test that is running, filtered by its levels.
+#[track_caller]
+pub fn get_logs() -> Records {
+    current_test().logs()
+}
+
+/// Returns a `Report` over everything the test that is running recorded.
+#[track_caller]
+pub fn get_report() -> Report {
+    current_test().report()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Filter;
+    use tracing::{Id, Level};
+
+    #[test]
+    fn tests_are_forgotten_when_they_end() {
+        let telemetry = TestTelemetry::new(Id::from_u64(u64::MAX), Filter::new(Level::INFO));
+
+        let registered = __register_test(&telemetry);
+        assert!(TESTS.lock().unwrap().contains_key(&u64::MAX));
+        drop(registered);
+        assert!(!TESTS.lock().unwrap().contains_key(&u64::MAX));
+    }
+}
@@ -0,0 +1,438 @@
+//! A structural diff between two [`Span`] trees.
+//!
+//! Span ids and source locations are ignored,
+//! spans are compared by name, level, fields, events and children.
+use std::fmt::{self, Display};
+
+use crate::record::RecordValue;
+use crate::report::Span;
+use crate::text::{event_to_string, value_to_string};
+
+/// A difference between two span trees.
+///
+/// Paths are made of span names separated by `/`,
+/// a span name is suffixed with `[index]` when several siblings share it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// A span only exists in the right tree
+    SpanAdded {
+        path: String,
+    },
+    /// A span only exists in the left tree
+    SpanRemoved {
+        path: String,
+    },
+    /// A span (and its subtree) moved to an other place in the tree
+    SpanMoved {
+        from: String,
+        to: String,
+    },
+    LevelChanged {
+        path: String,
+        left: String,
+        right: String,
+    },
+    /// A field has different values, an empty Vec means the field wasn't recorded
+    FieldChanged {
+        path: String,
+        field: String,
+        left: Vec<RecordValue>,
+        right: Vec<RecordValue>,
+    },
+    EventAdded {
+        path: String,
+        event: String,
+    },
+    EventRemoved {
+        path: String,
+        event: String,
+    },
+    EventChanged {
+        path: String,
+        left: String,
+        right: String,
+    },
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::SpanAdded { path } => write!(f, "+ span {path}"),
+            Change::SpanRemoved { path } => write!(f, "- span {path}"),
+            Change::SpanMoved { from, to } => write!(f, "~ span moved {from} -> {to}"),
+            Change::LevelChanged { path, left, right } => {
+                write!(f, "~ {path}: level {left} -> {right}")
+            }
+            Change::FieldChanged {
+                path,
+                field,
+                left,
+                right,
+            } => match (left.is_empty(), right.is_empty()) {
+                (true, _) => write!(f, "+ {path}: {field}={}", values_to_string(right)),
+                (_, true) => write!(f, "- {path}: {field}={}", values_to_string(left)),
+                _ => write!(
+                    f,
+                    "~ {path}: {field}={} -> {}",
+                    values_to_string(left),
+                    values_to_string(right)
+                ),
+            },
+            Change::EventAdded { path, event } => write!(f, "+ {path}: - {event}"),
+            Change::EventRemoved { path, event } => write!(f, "- {path}: - {event}"),
+            Change::EventChanged { path, left, right } => {
+                write!(f, "~ {path}: - {left}\n  {path}: - {right}")
+            }
+        }
+    }
+}
+
+fn values_to_string(values: &[RecordValue]) -> String {
+    values
+        .iter()
+        .map(value_to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The differences between two span trees, see [`Span::diff`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpanDiff {
+    changes: Vec<Change>,
+}
+
+impl SpanDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn changes(&self) -> impl Iterator<Item = &Change> {
+        self.changes.iter()
+    }
+}
+
+impl Display for SpanDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{change}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Span {
+    /// Compares two span trees, and lists what changed from `self` (left) to `other` (right).
+    pub fn diff(&self, other: &Span) -> SpanDiff {
+        let mut differ = Differ::default();
+        if self.name() == other.name() {
+            differ.span(self, other, self.name(), other.name());
+        } else {
+            differ.removed.push((self.name().to_string(), self));
+            differ.added.push((other.name().to_string(), other));
+        }
+        differ.finish()
+    }
+}
+
+#[derive(Default)]
+struct Differ<'a> {
+    changes: Vec<Change>,
+    removed: Vec<(String, &'a Span)>,
+    added: Vec<(String, &'a Span)>,
+}
+
+impl<'a> Differ<'a> {
+    fn span(&mut self, left: &'a Span, right: &'a Span, left_path: &str, right_path: &str) {
+        let (left_metadata, right_metadata) = (left.record().metadata(), right.record().metadata());
+        if left_metadata.level != right_metadata.level {
+            self.changes.push(Change::LevelChanged {
+                path: right_path.to_string(),
+                left: left_metadata.level,
+                right: right_metadata.level,
+            });
+        }
+
+        self.fields(left, right, right_path);
+
+        let left_events: Vec<_> = left
+            .events()
+            .map(|event| event_to_string(event, &left_metadata.target))
+            .collect();
+        let right_events: Vec<_> = right
+            .events()
+            .map(|event| event_to_string(event, &right_metadata.target))
+            .collect();
+        for edit in align(&left_events, &right_events, |l, r| l == r) {
+            let path = right_path.to_string();
+            self.changes.push(match edit {
+                Edit::Same(..) => continue,
+                Edit::Removed(l) => Change::EventRemoved {
+                    path,
+                    event: left_events[l].clone(),
+                },
+                Edit::Added(r) => Change::EventAdded {
+                    path,
+                    event: right_events[r].clone(),
+                },
+                Edit::Changed(l, r) => Change::EventChanged {
+                    path,
+                    left: left_events[l].clone(),
+                    right: right_events[r].clone(),
+                },
+            });
+        }
+
+        let left_children = children_paths(left, left_path);
+        let right_children = children_paths(right, right_path);
+        for edit in align(&left_children, &right_children, |(_, l), (_, r)| {
+            l.name() == r.name()
+        }) {
+            match edit {
+                Edit::Same(l, r) => {
+                    let ((left_path, left), (right_path, right)) =
+                        (&left_children[l], &right_children[r]);
+                    self.span(left, right, left_path, right_path);
+                }
+                Edit::Removed(l) => self.removed.push(left_children[l].clone()),
+                Edit::Added(r) => self.added.push(right_children[r].clone()),
+                Edit::Changed(l, r) => {
+                    self.removed.push(left_children[l].clone());
+                    self.added.push(right_children[r].clone());
+                }
+            }
+        }
+    }
+
+    fn fields(&mut self, left: &Span, right: &Span, path: &str) {
+        let mut names: Vec<&str> = Vec::new();
+        for (field, _) in left.fields().chain(right.fields()) {
+            if !names.contains(&field.as_str()) {
+                names.push(field);
+            }
+        }
+
+        for name in names {
+            let values = |span: &Span| -> Vec<RecordValue> {
+                span.fields()
+                    .filter(|(field, _)| field == name)
+                    .map(|(_, value)| value.clone())
+                    .collect()
+            };
+            let (left, right) = (values(left), values(right));
+            if left != right {
+                self.changes.push(Change::FieldChanged {
+                    path: path.to_string(),
+                    field: name.to_string(),
+                    left,
+                    right,
+                });
+            }
+        }
+    }
+
+    // A removed span that shows up, unchanged, somewhere else in the right tree has moved.
+    fn finish(mut self) -> SpanDiff {
+        let mut added = self.added;
+        for (from, removed) in self.removed {
+            let moved_to = added.iter().position(|(_, span)| {
+                span.name() == removed.name() && removed.diff(span).is_empty()
+            });
+            match moved_to {
+                Some(index) => {
+                    let (to, _) = added.remove(index);
+                    self.changes.push(Change::SpanMoved { from, to });
+                }
+                None => self.changes.push(Change::SpanRemoved { path: from }),
+            }
+        }
+        self.changes.extend(
+            added
+                .into_iter()
+                .map(|(path, _)| Change::SpanAdded { path }),
+        );
+        SpanDiff {
+            changes: self.changes,
+        }
+    }
+}
+
+/// The children of `span`, with their path.
+///
+/// A child name is suffixed with `[index]` when several siblings share it.
+pub(crate) fn children_paths<'a>(span: &'a Span, path: &str) -> Vec<(String, &'a Span)> {
+    let children: Vec<&Span> = span.children().collect();
+    let mut seen: Vec<&str> = Vec::new();
+    children
+        .iter()
+        .map(|child| {
+            let homonyms = children
+                .iter()
+                .filter(|other| other.name() == child.name())
+                .count();
+            let index = seen.iter().filter(|name| **name == child.name()).count();
+            seen.push(child.name());
+
+            let child_path = if homonyms > 1 {
+                format!("{path}/{}[{index}]", child.name())
+            } else {
+                format!("{path}/{}", child.name())
+            };
+            (child_path, *child)
+        })
+        .collect()
+}
+
+pub(crate) enum Edit {
+    Same(usize, usize),
+    Removed(usize),
+    Added(usize),
+    Changed(usize, usize),
+}
+
+// Aligns two sequences along their longest common subsequence.
+// Removals directly followed by additions are paired up as changes.
+pub(crate) fn align<T>(left: &[T], right: &[T], eq: impl Fn(&T, &T) -> bool) -> Vec<Edit> {
+    let (n, m) = (left.len(), right.len());
+    let mut lengths = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lengths[i][j] = if eq(&left[i], &right[j]) {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut edits = Vec::new();
+    let (mut removed, mut added) = (Vec::new(), Vec::new());
+    let flush = |edits: &mut Vec<Edit>, removed: &mut Vec<usize>, added: &mut Vec<usize>| {
+        let paired = removed.len().min(added.len());
+        edits.extend(
+            removed
+                .iter()
+                .zip(added.iter())
+                .map(|(&l, &r)| Edit::Changed(l, r)),
+        );
+        edits.extend(removed.drain(..).skip(paired).map(Edit::Removed));
+        edits.extend(added.drain(..).skip(paired).map(Edit::Added));
+    };
+
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && eq(&left[i], &right[j]) {
+            flush(&mut edits, &mut removed, &mut added);
+            edits.push(Edit::Same(i, j));
+            i += 1;
+            j += 1;
+        } else if j == m || (i < n && lengths[i + 1][j] >= lengths[i][j + 1]) {
+            removed.push(i);
+            i += 1;
+        } else {
+            added.push(j);
+            j += 1;
+        }
+    }
+    flush(&mut edits, &mut removed, &mut added);
+    edits
+}
+
+/// Asserts that two span trees are structurally equal (see [`Span::diff`]),
+/// and prints the differences between them otherwise.
+///
+/// ```ignore
+/// let expected: Span = include_str!("expected_tree.txt").parse().unwrap();
+/// assert_spans_eq!(expected, get_spans());
+/// ```
+#[macro_export]
+macro_rules! assert_spans_eq {
+    ($left:expr, $right:expr $(,)?) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                let diff = $crate::Span::diff(left, right);
+                if !diff.is_empty() {
+                    panic!("assertion `left == right` failed: span trees differ\n{}", diff);
+                }
+            }
+        }
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                let diff = $crate::Span::diff(left, right);
+                if !diff.is_empty() {
+                    panic!(
+                        "assertion `left == right` failed: {}\nspan trees differ\n{}",
+                        format_args!($($arg)+),
+                        diff
+                    );
+                }
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(text: &str) -> Span {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn equal_trees_have_no_diff() {
+        let tree = "root (INFO)\n  a (INFO) number=1\n    - INFO \"hi\"\n  b (INFO)\n";
+        assert!(span(tree).diff(&span(tree)).is_empty());
+        assert_spans_eq!(span(tree), span(tree));
+    }
+
+    #[test]
+    fn diff_reports_changes_by_path() {
+        let left = span(
+            r#"root (INFO)
+  a (INFO) number=1 gone=true
+    - INFO "hi"
+    - INFO "unchanged"
+  b (INFO)
+  b (INFO)
+    c (DEBUG)
+  removed (INFO)
+"#,
+        );
+        let right = span(
+            r#"root (INFO)
+  a (WARN) number=2 new="field"
+    - INFO "hello"
+    - INFO "unchanged"
+    - ERROR "boom"
+  b (INFO)
+    c (DEBUG)
+  b (INFO)
+  added (INFO)
+"#,
+        );
+
+        let diff = left.diff(&right).to_string();
+        assert_eq!(
+            r#"~ root/a: level INFO -> WARN
+~ root/a: number=1 -> 2
+- root/a: gone=true
++ root/a: new="field"
+~ root/a: - INFO "hi"
+  root/a: - INFO "hello"
++ root/a: - ERROR "boom"
+~ span moved root/b[1]/c -> root/b[0]/c
+- span root/removed
++ span root/added
+"#,
+            diff
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "span trees differ\n- span root/a\n")]
+    fn assert_spans_eq_prints_the_diff() {
+        assert_spans_eq!(span("root\n  a\n"), span("root\n"));
+    }
+}
@@ -0,0 +1,233 @@
+//! Diagnostics about spans exits that don't match what their thread entered,
+//! which is what holding an entered guard across an `.await` looks like.
+use crate::report::span_name;
+use crate::{Location, Report, TestTelemetry};
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::thread::ThreadId;
+
+/// An exit that doesn't match the enter stack of its thread, as the layer saw it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum MisplacedExit {
+    OtherThread {
+        entered_on: String,
+        exited_on: String,
+    },
+    OutOfOrder {
+        thread: String,
+        still_entered: Vec<u64>,
+    },
+}
+
+/// Takes `id` off the enter stack it is on, and tells what was wrong with the exit, if anything.
+pub(crate) fn pop(
+    entered: &mut HashMap<ThreadId, (String, Vec<u64>)>,
+    thread: ThreadId,
+    id: u64,
+) -> Option<MisplacedExit> {
+    let on_stack = |stack: &Vec<u64>| stack.iter().rposition(|entered| *entered == id);
+
+    let (misplaced, owner) =
+        if let Some(position) = entered.get(&thread).and_then(|(_, stack)| on_stack(stack)) {
+            let (name, stack) = entered.get_mut(&thread).expect("just found it; qed");
+            let still_entered = stack.split_off(position + 1);
+            stack.pop();
+            let misplaced = (!still_entered.is_empty()).then(|| MisplacedExit::OutOfOrder {
+                thread: name.clone(),
+                still_entered: still_entered.clone(),
+            });
+            // the spans entered after it are still entered, the next exits deal with them
+            stack.extend(still_entered);
+            (misplaced, thread)
+        } else {
+            let Some((&owner, position)) = entered
+                .iter()
+                .find_map(|(owner, (_, stack))| Some((owner, on_stack(stack)?)))
+            else {
+                // entered before the layer was set up
+                return None;
+            };
+            let (entered_on, stack) = entered.get_mut(&owner).expect("just found it; qed");
+            stack.remove(position);
+            let misplaced = MisplacedExit::OtherThread {
+                entered_on: entered_on.clone(),
+                exited_on: thread_name(thread, &std::thread::current()),
+            };
+            (Some(misplaced), owner)
+        };
+
+    if entered
+        .get(&owner)
+        .is_some_and(|(_, stack)| stack.is_empty())
+    {
+        entered.remove(&owner);
+    }
+    misplaced
+}
+
+pub(crate) fn thread_name(id: ThreadId, thread: &std::thread::Thread) -> String {
+    thread
+        .name()
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("{id:?}"))
+}
+
+/// A span exit that doesn't match what its thread entered, see `Report::enter_diagnostics`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnterDiagnostic {
+    /// The span was exited on another thread than the one it was entered on
+    ExitedOnAnotherThread {
+        /// Formatted like `UnclosedSpan::path`
+        span: String,
+        location: Location,
+        entered_on: String,
+        exited_on: String,
+    },
+    /// The span was exited while spans entered after it on the same thread were still entered
+    ExitedOutOfOrder {
+        span: String,
+        location: Location,
+        thread: String,
+        still_entered: Vec<String>,
+    },
+}
+
+impl Display for EnterDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnterDiagnostic::ExitedOnAnotherThread {
+                span,
+                location,
+                entered_on,
+                exited_on,
+            } => write!(
+                f,
+                "`{span}` (created {location}) was entered on thread `{entered_on}` but exited on thread `{exited_on}`, is an entered guard held across an `.await`?"
+            ),
+            EnterDiagnostic::ExitedOutOfOrder {
+                span,
+                location,
+                thread,
+                still_entered,
+            } => write!(
+                f,
+                "`{span}` (created {location}) was exited on thread `{thread}` while `{}`, entered after it, was still entered",
+                still_entered.join("`, `")
+            ),
+        }
+    }
+}
+
+impl Report {
+    /// The span exits under the root that didn't match what their thread entered, in the order they happened.
+    pub fn enter_diagnostics(&self) -> Vec<EnterDiagnostic> {
+        let name = |id: &u64| {
+            self.spans
+                .get(id)
+                .and_then(|recorder| recorder.metadata())
+                .map(span_name)
+                .unwrap_or_else(|| format!("span {id}"))
+        };
+
+        let mut diagnostics: Vec<_> = self
+            .node_to_id
+            .iter()
+            .filter(|(node, _)| self.is_under_root(**node))
+            .filter_map(|(node, id)| Some((node, self.spans.get(id)?)))
+            .flat_map(|(node, recorder)| {
+                let path = self.span_path(*node);
+                let location = recorder
+                    .metadata()
+                    .map(|metadata| metadata.location())
+                    .unwrap_or_default();
+                recorder
+                    .misplaced_exits()
+                    .iter()
+                    .map(move |(sequence, misplaced)| {
+                        let diagnostic = match misplaced {
+                            MisplacedExit::OtherThread {
+                                entered_on,
+                                exited_on,
+                            } => EnterDiagnostic::ExitedOnAnotherThread {
+                                span: path.clone(),
+                                location: location.clone(),
+                                entered_on: entered_on.clone(),
+                                exited_on: exited_on.clone(),
+                            },
+                            MisplacedExit::OutOfOrder {
+                                thread,
+                                still_entered,
+                            } => EnterDiagnostic::ExitedOutOfOrder {
+                                span: path.clone(),
+                                location: location.clone(),
+                                thread: thread.clone(),
+                                still_entered: still_entered.iter().map(name).collect(),
+                            },
+                        };
+                        (*sequence, diagnostic)
+                    })
+            })
+            .collect();
+        diagnostics.sort_by_key(|(sequence, _)| *sequence);
+        diagnostics
+            .into_iter()
+            .map(|(_, diagnostic)| diagnostic)
+            .collect()
+    }
+}
+
+#[doc(hidden)]
+pub fn __check_enter_diagnostics(telemetry: &TestTelemetry) -> Result<(), String> {
+    let diagnostics = telemetry.report().enter_diagnostics();
+    if diagnostics.is_empty() {
+        return Ok(());
+    }
+    let diagnostics: Vec<_> = diagnostics
+        .iter()
+        .map(|diagnostic| format!("  {diagnostic}"))
+        .collect();
+    Err(format!(
+        "spans were exited out of place:\n{}",
+        diagnostics.join("\n")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stacks(stacks: &[(ThreadId, &str, &[u64])]) -> HashMap<ThreadId, (String, Vec<u64>)> {
+        stacks
+            .iter()
+            .map(|(thread, name, stack)| (*thread, (name.to_string(), stack.to_vec())))
+            .collect()
+    }
+
+    #[test]
+    fn exits_are_checked_against_the_thread_stacks() {
+        let current = std::thread::current().id();
+        let other = std::thread::spawn(|| std::thread::current().id())
+            .join()
+            .unwrap();
+
+        let mut entered = stacks(&[(current, "main", &[1, 2, 3])]);
+        assert_eq!(None, pop(&mut entered, current, 3));
+        assert_eq!(
+            Some(MisplacedExit::OutOfOrder {
+                thread: "main".to_string(),
+                still_entered: vec![2]
+            }),
+            pop(&mut entered, current, 1)
+        );
+        assert_eq!(None, pop(&mut entered, current, 2));
+        assert!(entered.is_empty());
+
+        let mut entered = stacks(&[(other, "worker", &[4])]);
+        assert!(matches!(
+            pop(&mut entered, current, 4),
+            Some(MisplacedExit::OtherThread { entered_on, .. }) if entered_on == "worker"
+        ));
+        assert!(entered.is_empty());
+        assert_eq!(None, pop(&mut entered, current, 5));
+    }
+}
@@ -0,0 +1,121 @@
+//! Dumps the telemetry of tests that panic, which is when it is needed the most.
+use crate::{Records, Span};
+use std::panic::AssertUnwindSafe;
+use std::path::PathBuf;
+
+/// The environment variable that sets where failure dumps are written, on top of stderr.
+pub const ARTIFACTS_DIR: &str = "TEST_SPAN_ARTIFACTS_DIR";
+
+/// A guard that renders a test's span tree and logs when it is dropped while the test panics.
+///
+/// `#[test_span]` creates one for each test. Since it only acts while unwinding,
+/// it works with `#[should_panic]` tests, and the panic carries on once the dump is written.
+pub struct FailureDump<F: Fn() -> (Span, Records)> {
+    test_name: &'static str,
+    get_telemetry: F,
+    artifacts_dir: Option<PathBuf>,
+}
+
+impl<F: Fn() -> (Span, Records)> FailureDump<F> {
+    /// The dump is also written to `$TEST_SPAN_ARTIFACTS_DIR/<test_name>.txt` if the variable is set.
+    pub fn new(test_name: &'static str, get_telemetry: F) -> Self {
+        Self {
+            test_name,
+            get_telemetry,
+            artifacts_dir: std::env::var_os(ARTIFACTS_DIR).map(PathBuf::from),
+        }
+    }
+
+    pub fn artifacts_dir(mut self, artifacts_dir: Option<PathBuf>) -> Self {
+        self.artifacts_dir = artifacts_dir;
+        self
+    }
+
+    pub fn render(&self) -> String {
+        let (spans, logs) = (self.get_telemetry)();
+        format!("spans:\n{spans}\nlogs:\n{logs}")
+    }
+
+    fn dump(&self) {
+        // a second panic would abort the test run and hide the first one
+        let dump = match std::panic::catch_unwind(AssertUnwindSafe(|| self.render())) {
+            Ok(dump) => dump,
+            Err(_) => {
+                eprintln!(
+                    "test-span: `{}` panicked, and rendering what it recorded panicked too",
+                    self.test_name
+                );
+                return;
+            }
+        };
+        eprintln!(
+            "test-span: `{}` panicked, here is what it recorded\n{dump}",
+            self.test_name
+        );
+
+        if let Some(dir) = &self.artifacts_dir {
+            let path = dir.join(format!("{}.txt", self.test_name.replace("::", "__")));
+            // errors are reported rather than raised, a panic while unwinding would abort the test run
+            if let Err(e) = std::fs::create_dir_all(dir).and_then(|_| std::fs::write(&path, dump)) {
+                eprintln!("test-span: couldn't write {}: {e}", path.display());
+            } else {
+                eprintln!("test-span: wrote {}", path.display());
+            }
+        }
+    }
+}
+
+impl<F: Fn() -> (Span, Records)> Drop for FailureDump<F> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            self.dump();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn telemetry() -> (Span, Records) {
+        let spans =
+            "tests::root (INFO)\n  tests::do_stuff (INFO) number=52\n    - INFO \"here i am!\"\n";
+        (
+            spans.parse().unwrap(),
+            "message=?\"here i am!\"\n".parse().unwrap(),
+        )
+    }
+
+    #[test]
+    fn dumps_only_when_panicking() {
+        let dir = std::env::temp_dir().join(format!("test-span-dump-{}", std::process::id()));
+        let path = dir.join("tests__dumps.txt");
+
+        drop(FailureDump::new("tests::dumps", telemetry).artifacts_dir(Some(dir.clone())));
+        assert!(!path.exists());
+
+        let panicked = std::panic::catch_unwind(|| {
+            let _dump =
+                FailureDump::new("tests::dumps", telemetry).artifacts_dir(Some(dir.clone()));
+            panic!("boom");
+        });
+        assert!(panicked.is_err());
+        assert_eq!(
+            "spans:\ntests::root (INFO)\n  tests::do_stuff (INFO) number=52\n    - INFO \"here i am!\"\n\nlogs:\nmessage=?\"here i am!\"\n",
+            std::fs::read_to_string(&path).unwrap()
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_panicking_render_doesnt_abort() {
+        let panicked = std::panic::catch_unwind(|| {
+            let _dump = FailureDump::new("tests::render_panics", || -> (Span, Records) {
+                panic!("can't fetch the telemetry")
+            })
+            .artifacts_dir(None);
+            panic!("boom");
+        });
+        assert!(panicked.is_err());
+    }
+}
@@ -3,12 +3,19 @@ use ::tracing::span;
 use ::tracing::{Event, Metadata};
 use indexmap::IndexMap;
 use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread::ThreadId;
 use tracing_subscriber::layer::Context;
 use tracing_subscriber::registry::LookupSpan;
 
+use crate::coverage;
+use crate::enters;
 use crate::log::LogsRecorder;
 use crate::record::Recorder;
 use crate::report::ALL_DAGS;
+use crate::subscription::{self, Notification};
+use crate::wait;
 use crate::LazyMutex;
 
 pub(crate) static ALL_SPANS: LazyMutex<IndexMap<u64, Recorder>> = Lazy::new(Default::default);
@@ -17,6 +24,17 @@ pub(crate) static SPAN_ID_TO_ROOT_AND_NODE_INDEX: LazyMutex<
     IndexMap<u64, (u64, daggy::NodeIndex)>,
 > = Lazy::new(Default::default);
 
+/// The spans each thread is in, innermost last, along with the thread's name.
+pub(crate) static ENTERED: LazyMutex<HashMap<ThreadId, (String, Vec<u64>)>> =
+    Lazy::new(Default::default);
+
+// Orders span opens, span closes and events across all threads.
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+pub(crate) fn next_sequence() -> u64 {
+    SEQUENCE.fetch_add(1, Ordering::SeqCst)
+}
+
 #[derive(Debug, Default)]
 pub struct Layer {}
 
@@ -30,6 +48,40 @@ impl Layer {
             .record(record);
     }
 
+    fn enter(&self, id: &span::Id) {
+        if let Some(recorder) = ALL_SPANS.lock().unwrap().get_mut(&id.into_u64()) {
+            recorder.enter();
+        }
+        let thread = std::thread::current();
+        ENTERED
+            .lock()
+            .unwrap()
+            .entry(thread.id())
+            .or_insert_with(|| (enters::thread_name(thread.id(), &thread), Vec::new()))
+            .1
+            .push(id.into_u64());
+    }
+
+    fn exit(&self, id: &span::Id) {
+        let misplaced = enters::pop(
+            &mut ENTERED.lock().unwrap(),
+            std::thread::current().id(),
+            id.into_u64(),
+        );
+        if let Some(recorder) = ALL_SPANS.lock().unwrap().get_mut(&id.into_u64()) {
+            recorder.exit();
+            if let Some(misplaced) = misplaced {
+                recorder.misplaced_exit(misplaced);
+            }
+        }
+    }
+
+    fn close(&self, id: span::Id) {
+        if let Some(recorder) = ALL_SPANS.lock().unwrap().get_mut(&id.into_u64()) {
+            recorder.close();
+        }
+    }
+
     fn event(&self, event: &Event<'_>, ctx: Context<'_, impl tracing::Subscriber>) {
         let current_span = ctx.current_span();
         let current_span = current_span.id().cloned();
@@ -44,6 +96,15 @@ impl Layer {
     ) {
         let raw_span_id = span_id.into_u64();
 
+        // recorded before the span is published in the id map,
+        // so a report that finds the span in there also finds its recorder
+        ALL_SPANS
+            .lock()
+            .unwrap()
+            .entry(raw_span_id)
+            .or_default()
+            .attributes(span_id, attributes);
+
         if let Some(id) = parent_id {
             // We have a parent, we can store the span in the right DAG
             let raw_parent_id = id.into_u64();
@@ -68,22 +129,11 @@ impl Layer {
             let mut new_dag: Dag<u64, ()> = Default::default();
             let root_index = new_dag.add_node(raw_span_id);
 
-            // The span is the root here
-            SPAN_ID_TO_ROOT_AND_NODE_INDEX
-                .lock()
-                .unwrap()
-                .insert(raw_span_id, (raw_span_id, root_index));
-
-            let mut all_dags = ALL_DAGS.lock().unwrap();
-            all_dags.insert(raw_span_id, new_dag);
+            // The span is the root here, the dag is stored before the id map lock is released
+            let mut id_to_node_index = SPAN_ID_TO_ROOT_AND_NODE_INDEX.lock().unwrap();
+            ALL_DAGS.lock().unwrap().insert(raw_span_id, new_dag);
+            id_to_node_index.insert(raw_span_id, (raw_span_id, root_index));
         }
-
-        ALL_SPANS
-            .lock()
-            .unwrap()
-            .entry(raw_span_id)
-            .or_default()
-            .attributes(span_id, attributes);
     }
 }
 
@@ -93,8 +143,9 @@ where
 {
     fn register_callsite(
         &self,
-        _metadata: &'static Metadata<'static>,
+        metadata: &'static Metadata<'static>,
     ) -> tracing::subscriber::Interest {
+        coverage::register(metadata);
         tracing::subscriber::Interest::always()
     }
 
@@ -104,24 +155,68 @@ where
         id: &span::Id,
         ctx: tracing_subscriber::layer::Context<'_, S>,
     ) {
-        let maybe_parent_id = attrs
-            .parent()
-            .cloned()
-            .or_else(|| ctx.current_span().id().cloned());
-
-        self.attributes(id.clone(), attrs, maybe_parent_id)
+        // spans created with `parent: None` are roots, even inside an other span
+        let maybe_parent_id = if attrs.is_contextual() {
+            ctx.current_span().id().cloned()
+        } else {
+            attrs.parent().cloned()
+        };
+
+        coverage::hit(
+            attrs.metadata(),
+            maybe_parent_id.as_ref().map(span::Id::into_u64),
+        );
+        self.attributes(id.clone(), attrs, maybe_parent_id);
+        subscription::publish(id.into_u64(), || Notification::SpanOpened {
+            id: id.into_u64(),
+            name: span_name(attrs.metadata()),
+            fields: subscription::fields(|visitor| attrs.record(visitor)),
+        });
+        wait::changed();
     }
 
     fn on_record(
         &self,
         span: &span::Id,
         values: &span::Record<'_>,
-        _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ctx: tracing_subscriber::layer::Context<'_, S>,
     ) {
-        self.record(span.clone(), values)
+        self.record(span.clone(), values);
+        subscription::publish(span.into_u64(), || Notification::FieldsRecorded {
+            id: span.into_u64(),
+            name: ctx.metadata(span).map(span_name).unwrap_or_default(),
+            fields: subscription::fields(|visitor| values.record(visitor)),
+        });
+        wait::changed();
     }
 
     fn on_event(&self, event: &Event<'_>, ctx: tracing_subscriber::layer::Context<'_, S>) {
-        self.event(event, ctx)
+        let span_id = ctx.current_span().id().map(span::Id::into_u64);
+        coverage::hit(event.metadata(), span_id);
+        self.event(event, ctx);
+        if let Some(span_id) = span_id {
+            subscription::publish(span_id, || subscription::event(span_id, event));
+        }
+        wait::changed();
+    }
+
+    fn on_enter(&self, id: &span::Id, _ctx: tracing_subscriber::layer::Context<'_, S>) {
+        self.enter(id)
+    }
+
+    fn on_exit(&self, id: &span::Id, _ctx: tracing_subscriber::layer::Context<'_, S>) {
+        self.exit(id)
+    }
+
+    fn on_close(&self, id: span::Id, ctx: tracing_subscriber::layer::Context<'_, S>) {
+        let name = ctx.metadata(&id).map(span_name).unwrap_or_default();
+        let raw_id = id.into_u64();
+        self.close(id);
+        subscription::publish(raw_id, || Notification::SpanClosed { id: raw_id, name });
+        wait::changed();
     }
 }
+
+fn span_name(metadata: &Metadata<'_>) -> String {
+    format!("{}::{}", metadata.target(), metadata.name())
+}
@@ -0,0 +1,56 @@
+//! Spans that are never closed, because something holds on to them.
+use crate::{Location, Report, TestTelemetry};
+use std::fmt::{self, Display};
+
+/// A span that wasn't closed yet, see `Report::unclosed_spans`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnclosedSpan {
+    pub id: u64,
+    /// The names of the spans enclosing it, from the root down to the span itself, separated by `/`
+    pub path: String,
+    pub location: Location,
+}
+
+impl Display for UnclosedSpan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} created {}", self.path, self.location)
+    }
+}
+
+impl Report {
+    /// The spans under the root that aren't closed yet, the root itself aside, in the order they were created.
+    pub fn unclosed_spans(&self) -> Vec<UnclosedSpan> {
+        let mut nodes: Vec<_> = self.node_to_id.iter().collect();
+        nodes.sort();
+        nodes
+            .into_iter()
+            .filter(|(node, id)| **id != self.root_id && self.is_under_root(**node))
+            .filter_map(|(node, id)| {
+                let recorder = self.spans.get(id)?;
+                let metadata = recorder.metadata()?;
+                if recorder.closed_sequence().is_some() {
+                    return None;
+                }
+                Some(UnclosedSpan {
+                    id: *id,
+                    path: self.span_path(*node),
+                    location: metadata.location(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[doc(hidden)]
+pub fn __check_unclosed_spans(telemetry: &TestTelemetry) -> Result<(), String> {
+    let unclosed = telemetry.report().unclosed_spans();
+    if unclosed.is_empty() {
+        return Ok(());
+    }
+    let spans: Vec<_> = unclosed.iter().map(|span| format!("  {span}")).collect();
+    Err(format!(
+        "{} span(s) weren't closed when the test returned:\n{}",
+        unclosed.len(),
+        spans.join("\n")
+    ))
+}
@@ -55,15 +55,80 @@ use tracing_core::dispatcher;
 use tracing_subscriber::{prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt};
 type LazyMutex<T> = Lazy<Arc<Mutex<T>>>;
 
+mod assertions;
 mod attribute;
+mod capture;
+mod checkpoint;
+mod concurrency;
+mod coverage;
+mod current;
+mod diff;
+mod enters;
+mod failure;
 mod layer;
+mod leaks;
 mod log;
+mod matcher;
+mod ordering;
 mod record;
 mod report;
+mod schema;
+mod snapshot;
+mod subscription;
+mod telemetry;
+mod text;
+mod unrecorded;
+mod wait;
+mod watchdog;
+mod zipkin;
 
+#[doc(hidden)]
+pub use assertions::{__check_log, __check_spans};
+pub use assertions::{Count, SpanQuery};
+pub use attribute::{Location, OwnedFieldSet, OwnedMetadata};
+pub use capture::{capture, capture_async, start, Session};
+pub use checkpoint::{checkpoint, Checkpoint};
+#[doc(hidden)]
+pub use concurrency::__check_overlap;
+pub use concurrency::ActiveInterval;
+#[doc(hidden)]
+pub use coverage::__CoverageReport;
+pub use coverage::{Callsite, CallsiteKind, Coverage, COVERAGE_DIR};
+#[doc(hidden)]
+pub use current::{__RegisteredTest, __register_test};
+pub use current::{get_logs, get_report, get_spans, get_telemetry};
+pub use diff::{Change, SpanDiff};
+#[doc(hidden)]
+pub use enters::__check_enter_diagnostics;
+pub use enters::EnterDiagnostic;
+pub use failure::{FailureDump, ARTIFACTS_DIR};
 pub use layer::Layer;
-pub use record::{Record, RecordValue};
+#[doc(hidden)]
+pub use leaks::__check_unclosed_spans;
+pub use leaks::UnclosedSpan;
+pub use log::LogEvent;
+pub use matcher::{EventPattern, MatchError, SpanPattern};
+#[doc(hidden)]
+pub use ordering::__check_before;
+pub use ordering::{event, span, Moment, SpanMoments};
+pub use record::{Record, RecordValue, RecordWithMetadata};
 pub use report::{Filter, Records, Report, Span};
+#[doc(hidden)]
+pub use schema::__check_schema;
+pub use schema::{FieldType, Schema, SchemaViolation, SpanSchema, ViolationKind};
+#[doc(hidden)]
+pub use snapshot::__golden_path;
+pub use snapshot::{assert_golden, SnapshotFormat, SnapshotOptions, REDACTED, UPDATE_SNAPSHOTS};
+pub use subscription::{Notification, Subscription};
+pub use telemetry::TestTelemetry;
+pub use test_span_macro::span_tree;
+pub use text::ParseError;
+#[doc(hidden)]
+pub use unrecorded::__check_unrecorded_fields;
+pub use unrecorded::UnrecordedFields;
+pub use wait::WaitTimeout;
+pub use watchdog::Watchdog;
+pub use zipkin::{Annotation, Endpoint, ZipkinSpan};
 
 static INIT: Lazy<()> = Lazy::new(|| {
     if dispatcher::has_been_set() {
@@ -89,7 +154,7 @@ pub fn init() {
 pub fn get_all_logs(filter: &Filter) -> Records {
     let logs = layer::ALL_LOGS.lock().unwrap().clone();
 
-    Records::new(logs.all_records_for_filter(filter))
+    Records::new(logs.all_records_for_filter(filter)).with_events(report::all_events(&logs, filter))
 }
 
 /// Returns both the output of `get_spans_for_root` and `get_logs_for_root`
@@ -107,6 +172,13 @@ pub fn get_spans_for_root(root_id: &Id, filter: &Filter) -> Span {
     Report::from_root(root_id.into_u64()).spans(filter)
 }
 
+/// Returns a `Report` over everything recorded under `root_id`, regardless of levels.
+///
+/// It is a snapshot: spans and logs recorded after the call won't show up.
+pub fn get_report_for_root(root_id: &Id) -> Report {
+    Report::from_root(root_id.into_u64())
+}
+
 /// Returns Records, which is a Vec, containing all entries recorded by children of `root_id`.
 ///
 /// This function filters the `Records`to only return the ones that match the set verbosity level.
@@ -117,12 +189,20 @@ pub fn get_logs_for_root(root_id: &Id, filter: &Filter) -> Records {
 }
 
 pub mod prelude {
-    pub use crate::{get_all_logs, get_logs_for_root, get_spans_for_root, get_telemetry_for_root};
-    pub use test_span_macro::test_span;
+    pub use crate::assert_schema;
+    pub use crate::{assert_before, assert_spans_eq, assert_spans_match, checkpoint};
+    pub use crate::{assert_concurrent, assert_sequential};
+    pub use crate::{assert_log, assert_no_log, assert_no_span, assert_span, assert_span_count};
+    pub use crate::{get_all_logs, get_logs_for_root, get_report_for_root};
+    pub use crate::{get_logs, get_report, get_spans, get_telemetry, TestTelemetry};
+    pub use crate::{get_spans_for_root, get_telemetry_for_root};
+    pub use test_span_macro::{span_tree, test_span};
 }
 
 pub mod reexports {
     pub use daggy;
+    #[cfg(feature = "insta")]
+    pub use insta;
     pub use serde;
     pub use tracing;
     pub use tracing_futures;
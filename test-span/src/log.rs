@@ -1,16 +1,90 @@
 use std::collections::HashSet;
+use std::time::SystemTime;
 
 use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
 use tracing::Event;
 
 use crate::{
     attribute::OwnedMetadata,
-    record::{Record, RecordEverything},
+    layer::next_sequence,
+    record::{Record, RecordEverything, RecordValue},
 };
 
+/// An event (a log line), and the fields it recorded.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LogEvent {
+    metadata: OwnedMetadata,
+    entries: Vec<Record>,
+    // the names of the spans the event was emitted in, outermost first
+    #[serde(default)]
+    scope: Vec<String>,
+}
+
+impl LogEvent {
+    pub fn new(metadata: OwnedMetadata, entries: Vec<Record>) -> Self {
+        Self {
+            metadata,
+            entries,
+            scope: Vec::new(),
+        }
+    }
+
+    pub fn with_scope(self, scope: Vec<String>) -> Self {
+        Self { scope, ..self }
+    }
+
+    /// The names of the spans the event was emitted in, from the root span down to its direct parent
+    pub fn scope(&self) -> impl Iterator<Item = &str> {
+        self.scope.iter().map(String::as_str)
+    }
+
+    pub fn metadata(&self) -> &OwnedMetadata {
+        &self.metadata
+    }
+
+    pub(crate) fn entries_mut(&mut self) -> impl Iterator<Item = &mut Record> {
+        self.entries.iter_mut()
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &Record> {
+        self.entries.iter()
+    }
+
+    /// The event message, if it has one.
+    pub fn message(&self) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(field, _)| field == "message")
+            .and_then(|(_, value)| match value {
+                RecordValue::Debug(message) | RecordValue::Error(message) => Some(message.as_str()),
+                RecordValue::Value(serde_json::Value::String(message)) => Some(message.as_str()),
+                RecordValue::Value(_) => None,
+            })
+    }
+}
+
+/// A single event, as it was emitted.
+#[derive(Debug, Clone)]
+pub(crate) struct RecordedEvent {
+    pub(crate) metadata: OwnedMetadata,
+    pub(crate) visitor: RecordEverything,
+    pub(crate) timestamp: SystemTime,
+    pub(crate) sequence: u64,
+}
+
+impl From<&RecordedEvent> for LogEvent {
+    fn from(event: &RecordedEvent) -> Self {
+        Self::new(
+            event.metadata.clone(),
+            event.visitor.contents().cloned().collect(),
+        )
+    }
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct LogsRecorder {
-    recorders: IndexMap<OwnedMetadata, RecordEverything>,
+    events: Vec<RecordedEvent>,
 }
 
 impl LogsRecorder {
@@ -21,43 +95,98 @@ impl LogsRecorder {
         } else {
             metadata
         };
-        event.record(self.recorders.entry(metadata).or_default())
+        let mut visitor = RecordEverything::default();
+        event.record(&mut visitor);
+
+        self.events.push(RecordedEvent {
+            metadata,
+            visitor,
+            timestamp: SystemTime::now(),
+            sequence: next_sequence(),
+        })
     }
 
     pub fn for_spans(&self, spans: HashSet<u64>) -> Self {
         Self {
-            recorders: self
-                .recorders
+            events: self
+                .events
+                .iter()
+                .filter(|event| matches!(event.metadata.span_id, Some(id) if spans.contains(&id)))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// The events with a sequence number in `after..before`,
+    /// moved to the span `span_id` maps to, or dropped if it maps to none.
+    pub(crate) fn window(
+        &self,
+        after: u64,
+        before: u64,
+        span_id: impl Fn(u64) -> Option<u64>,
+    ) -> Self {
+        Self {
+            events: self
+                .events
                 .iter()
-                .filter_map(|(log_metadata, visitor)| match log_metadata.span_id {
-                    Some(id) if spans.contains(&id) => {
-                        Some((log_metadata.clone(), visitor.clone()))
-                    }
-                    _ => None,
+                .filter(|event| after < event.sequence && event.sequence < before)
+                .filter_map(|event| {
+                    let id = span_id(event.metadata.span_id?)?;
+                    let mut event = event.clone();
+                    event.metadata.span_id = Some(id);
+                    Some(event)
                 })
                 .collect(),
         }
     }
 
+    /// Events emitted while `span_id` was the current span, in the order they happened.
+    pub(crate) fn events_for_span_id_and_filter<'a>(
+        &'a self,
+        span_id: u64,
+        filter: &'a crate::Filter,
+    ) -> impl Iterator<Item = &'a RecordedEvent> + 'a {
+        self.events.iter().filter(move |event| {
+            filter.is_enabled(&event.metadata) && event.metadata.span_id == Some(span_id)
+        })
+    }
+
     pub fn record_for_span_id_and_filter(
         &self,
         span_id: u64,
         filter: &crate::Filter,
     ) -> Vec<Record> {
-        self.recorders
+        grouped_by_callsite(self.events_for_span_id_and_filter(span_id, filter))
+    }
+
+    /// All events that match `filter`, in the order they happened.
+    pub(crate) fn all_events_for_filter<'a>(
+        &'a self,
+        filter: &'a crate::Filter,
+    ) -> impl Iterator<Item = &'a RecordedEvent> + 'a {
+        self.events
             .iter()
-            .filter(|(log_metadata, _recorders)| {
-                filter.is_enabled(log_metadata) && log_metadata.span_id == Some(span_id)
-            })
-            .flat_map(|(_log_metadata, recorders)| recorders.contents().cloned())
-            .collect()
+            .filter(|event| filter.is_enabled(&event.metadata))
     }
 
     pub fn all_records_for_filter(&self, filter: &crate::Filter) -> Vec<Record> {
-        self.recorders
-            .iter()
-            .filter(|(log_metadata, _recorders)| filter.is_enabled(log_metadata))
-            .flat_map(|(_log_metadata, recorders)| recorders.contents().cloned())
-            .collect()
+        grouped_by_callsite(
+            self.events
+                .iter()
+                .filter(|event| filter.is_enabled(&event.metadata)),
+        )
+    }
+}
+
+// Records are reported grouped by callsite (and span),
+// in the order each callsite was first hit.
+fn grouped_by_callsite<'a>(events: impl Iterator<Item = &'a RecordedEvent>) -> Vec<Record> {
+    let mut by_callsite: IndexMap<&OwnedMetadata, Vec<Record>> = IndexMap::new();
+    for event in events {
+        by_callsite
+            .entry(&event.metadata)
+            .or_default()
+            .extend(event.visitor.contents().cloned());
     }
+    by_callsite.into_values().flatten().collect()
 }
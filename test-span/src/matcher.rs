@@ -0,0 +1,678 @@
+//! Match an expected skeleton against a captured [`Span`] tree.
+//!
+//! Unlike [`Span::diff`], a pattern only describes what must be there:
+//! extra spans, fields and events in the actual tree are allowed.
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
+
+use regex::Regex;
+use tracing::Level;
+
+use crate::diff::children_paths;
+use crate::log::LogEvent;
+use crate::record::{Record, RecordValue};
+use crate::report::Span;
+use crate::text::value_to_string;
+
+/// A pattern for a span and (some of) its descendants.
+///
+/// ```ignore
+/// let pattern = SpanPattern::new("root").child(
+///     SpanPattern::new("my_crate::do_stuff")
+///         .field("number", 52)
+///         .descendant(SpanPattern::new("do_stuff2").event(EventPattern::new().message("here i am!"))),
+/// );
+/// assert_spans_match!(get_spans(), pattern);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SpanPattern {
+    name: Option<String>,
+    level: Option<String>,
+    fields: Vec<Record>,
+    events: Vec<EventPattern>,
+    children: Vec<(Depth, SpanPattern)>,
+    ordered: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Depth {
+    Child,
+    Descendant,
+}
+
+impl SpanPattern {
+    /// A pattern for spans called `name`.
+    ///
+    /// The name matches the whole span name (`target::name`),
+    /// or its last `::` separated segments: `do_stuff` matches `my_crate::do_stuff`.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: Some(name.into()),
+            ..Default::default()
+        }
+    }
+
+    /// A pattern that matches spans regardless of their name.
+    pub fn any() -> Self {
+        Self::default()
+    }
+
+    pub fn level(self, level: Level) -> Self {
+        Self {
+            level: Some(level.to_string()),
+            ..self
+        }
+    }
+
+    /// The span must have recorded `value` for `field`.
+    pub fn field(mut self, field: impl Into<String>, value: impl Into<serde_json::Value>) -> Self {
+        self.fields.push((field.into(), RecordValue::value(value)));
+        self
+    }
+
+    /// An event that must have been emitted in the span.
+    pub fn event(mut self, event: EventPattern) -> Self {
+        self.events.push(event);
+        self
+    }
+
+    /// A span that must be a direct child of the span.
+    pub fn child(mut self, child: SpanPattern) -> Self {
+        self.children.push((Depth::Child, child));
+        self
+    }
+
+    /// A span that must be somewhere below the span, at any depth.
+    pub fn descendant(mut self, descendant: SpanPattern) -> Self {
+        self.children.push((Depth::Descendant, descendant));
+        self
+    }
+
+    /// Children, descendants and events must appear in the same order as in the pattern.
+    ///
+    /// Descendants are ordered depth first. Patterns are unordered by default.
+    pub fn ordered(self) -> Self {
+        Self {
+            ordered: true,
+            ..self
+        }
+    }
+
+    /// Checks whether the pattern matches `span`, the root of the pattern is matched against `span` itself.
+    pub fn matches(&self, span: &Span) -> Result<(), MatchError> {
+        self.match_span(span, span.name())
+    }
+
+    fn match_span(&self, span: &Span, path: &str) -> Result<(), MatchError> {
+        let mismatch = |reason: String| MatchError {
+            path: path.to_string(),
+            reason,
+        };
+
+        if let Some(name) = &self.name {
+            if !name_matches(name, span.name()) {
+                return Err(mismatch(format!("expected a span named `{name}`")));
+            }
+        }
+        if let Some(level) = &self.level {
+            let actual = span.record().metadata().level;
+            if level != &actual {
+                return Err(mismatch(format!("expected level {level}, found {actual}")));
+            }
+        }
+        for (field, expected) in &self.fields {
+            let values: Vec<&RecordValue> = span
+                .fields()
+                .filter(|(name, _)| name == field)
+                .map(|(_, value)| value)
+                .collect();
+            if !values.iter().any(|actual| value_matches(expected, actual)) {
+                let found = if values.is_empty() {
+                    "it wasn't recorded".to_string()
+                } else {
+                    format!(
+                        "found {}",
+                        values
+                            .into_iter()
+                            .map(value_to_string)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )
+                };
+                return Err(mismatch(format!(
+                    "expected {field}={}, {found}",
+                    value_to_string(expected)
+                )));
+            }
+        }
+
+        let events: Vec<&LogEvent> = span.events().collect();
+        let candidates: Vec<Vec<usize>> = self
+            .events
+            .iter()
+            .map(|pattern| {
+                (0..events.len())
+                    .filter(|&index| pattern.matches(events[index]))
+                    .collect()
+            })
+            .collect();
+        if let Some(missing) = candidates.iter().position(Vec::is_empty) {
+            return Err(mismatch(format!(
+                "no event matches {}",
+                self.events[missing]
+            )));
+        }
+        if !assign(&candidates, self.ordered) {
+            return Err(mismatch(
+                "the events don't match the pattern's events in the expected order".to_string(),
+            ));
+        }
+
+        // children and descendants are numbered depth first, which gives their order.
+        let descendants = descendants(span, path);
+        let mut candidates = Vec::with_capacity(self.children.len());
+        for (depth, pattern) in &self.children {
+            let mut deepest_error = None;
+            let matching: Vec<usize> = descendants
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, _, is_child))| *is_child || *depth == Depth::Descendant)
+                .filter(
+                    |(_, (path, descendant, _))| match pattern.match_span(descendant, path) {
+                        Ok(()) => true,
+                        Err(error) => {
+                            // A span with the right name is the most likely culprit
+                            let named = pattern
+                                .name
+                                .as_ref()
+                                .is_some_and(|name| name_matches(name, descendant.name()));
+                            if named && deepest_error.is_none() {
+                                deepest_error = Some(error);
+                            }
+                            false
+                        }
+                    },
+                )
+                .map(|(index, _)| index)
+                .collect();
+
+            if matching.is_empty() {
+                return Err(deepest_error.unwrap_or_else(|| {
+                    let relation = match depth {
+                        Depth::Child => "child",
+                        Depth::Descendant => "descendant",
+                    };
+                    mismatch(format!(
+                        "no {relation} span matches `{}`",
+                        pattern.name.as_deref().unwrap_or("*")
+                    ))
+                }));
+            }
+            candidates.push(matching);
+        }
+        if !assign(&candidates, self.ordered) {
+            return Err(mismatch(if self.ordered {
+                "the spans don't match the pattern's children in the expected order".to_string()
+            } else {
+                "there aren't enough distinct spans to match the pattern's children".to_string()
+            }));
+        }
+
+        Ok(())
+    }
+}
+
+impl From<&Span> for SpanPattern {
+    /// A pattern that matches the span tree and anything that contains it,
+    /// children and events must appear in the same order.
+    fn from(span: &Span) -> Self {
+        let metadata = span.record().metadata();
+        Self {
+            name: Some(span.name().to_string()),
+            level: Some(metadata.level).filter(|level| !level.is_empty()),
+            fields: span.fields().cloned().collect(),
+            events: span.events().map(EventPattern::from).collect(),
+            children: span
+                .children()
+                .map(|child| (Depth::Child, SpanPattern::from(child)))
+                .collect(),
+            ordered: true,
+        }
+    }
+}
+
+/// A pattern for an event (a log line).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct EventPattern {
+    level: Option<String>,
+    target: Option<String>,
+    span: Option<String>,
+    message: Option<MessagePattern>,
+    fields: Vec<Record>,
+}
+
+#[derive(Debug, Clone)]
+enum MessagePattern {
+    Exact(String),
+    Regex(Regex),
+}
+
+impl PartialEq for MessagePattern {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (MessagePattern::Exact(a), MessagePattern::Exact(b)) => a == b,
+            (MessagePattern::Regex(a), MessagePattern::Regex(b)) => a.as_str() == b.as_str(),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for MessagePattern {}
+
+impl Display for MessagePattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessagePattern::Exact(message) => {
+                f.write_str(&serde_json::Value::from(message.as_str()).to_string())
+            }
+            MessagePattern::Regex(regex) => write!(f, "=~ /{}/", regex.as_str()),
+        }
+    }
+}
+
+impl EventPattern {
+    /// A pattern that matches any event.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn level(self, level: Level) -> Self {
+        Self {
+            level: Some(level.to_string()),
+            ..self
+        }
+    }
+
+    /// The event target must be `target`, or one of its submodules.
+    pub fn target(self, target: impl Into<String>) -> Self {
+        Self {
+            target: Some(target.into()),
+            ..self
+        }
+    }
+
+    /// The event must have been emitted in a span called `name`, or in one of its descendants.
+    ///
+    /// Span names match like they do in [`SpanPattern::new`].
+    pub fn in_span(self, name: impl Into<String>) -> Self {
+        Self {
+            span: Some(name.into()),
+            ..self
+        }
+    }
+
+    pub fn message(self, message: impl Into<String>) -> Self {
+        Self {
+            message: Some(MessagePattern::Exact(message.into())),
+            ..self
+        }
+    }
+
+    /// The event message must match the `regex`.
+    ///
+    /// # Panics
+    ///
+    /// if `regex` is not a valid regular expression.
+    pub fn message_matches(self, regex: impl AsRef<str>) -> Self {
+        let regex = Regex::new(regex.as_ref())
+            .unwrap_or_else(|e| panic!("invalid message regex `{}`: {e}", regex.as_ref()));
+        Self {
+            message: Some(MessagePattern::Regex(regex)),
+            ..self
+        }
+    }
+
+    /// The event must have recorded `value` for `field`.
+    pub fn field(mut self, field: impl Into<String>, value: impl Into<serde_json::Value>) -> Self {
+        self.fields.push((field.into(), RecordValue::value(value)));
+        self
+    }
+
+    pub fn matches(&self, event: &LogEvent) -> bool {
+        self.mismatches(event).is_empty()
+    }
+
+    /// Describes each part of the pattern `event` doesn't match.
+    pub fn mismatches(&self, event: &LogEvent) -> Vec<String> {
+        let metadata = event.metadata();
+        let mut mismatches = Vec::new();
+
+        if let Some(level) = &self.level {
+            if level != &metadata.level {
+                mismatches.push(format!("level is {}, expected {level}", metadata.level));
+            }
+        }
+        if let Some(target) = &self.target {
+            if !target_matches(target, &metadata.target) {
+                mismatches.push(format!(
+                    "target is `{}`, expected `{target}`",
+                    metadata.target
+                ));
+            }
+        }
+        if let Some(span) = &self.span {
+            if !event.scope().any(|name| name_matches(span, name)) {
+                mismatches.push(format!(
+                    "emitted in `{}`, expected in span `{span}`",
+                    event.scope().collect::<Vec<_>>().join("/")
+                ));
+            }
+        }
+        if let Some(pattern) = &self.message {
+            let matches = event.message().is_some_and(|message| match pattern {
+                MessagePattern::Exact(expected) => message == expected,
+                MessagePattern::Regex(regex) => regex.is_match(message),
+            });
+            if !matches {
+                let actual = event
+                    .message()
+                    .map(|message| serde_json::Value::from(message).to_string())
+                    .unwrap_or_else(|| "missing".to_string());
+                mismatches.push(format!("message is {actual}, expected {pattern}"));
+            }
+        }
+        for (field, expected) in &self.fields {
+            let values: Vec<&RecordValue> = event
+                .entries()
+                .filter(|(name, _)| name == field)
+                .map(|(_, value)| value)
+                .collect();
+            if !values.iter().any(|actual| value_matches(expected, actual)) {
+                let actual = if values.is_empty() {
+                    "wasn't recorded".to_string()
+                } else {
+                    format!(
+                        "is {}",
+                        values
+                            .into_iter()
+                            .map(value_to_string)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )
+                };
+                mismatches.push(format!(
+                    "{field} {actual}, expected {}",
+                    value_to_string(expected)
+                ));
+            }
+        }
+
+        mismatches
+    }
+}
+
+impl From<&str> for EventPattern {
+    fn from(message: &str) -> Self {
+        Self::new().message(message)
+    }
+}
+
+impl From<String> for EventPattern {
+    fn from(message: String) -> Self {
+        Self::new().message(message)
+    }
+}
+
+impl From<&LogEvent> for EventPattern {
+    fn from(event: &LogEvent) -> Self {
+        Self {
+            level: Some(event.metadata().level.clone()),
+            target: Some(event.metadata().target.clone()),
+            fields: event.entries().cloned().collect(),
+            ..Default::default()
+        }
+    }
+}
+
+impl Display for EventPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        parts.extend(self.level.clone());
+        parts.extend(self.target.as_ref().map(|target| format!("{target}:")));
+        parts.extend(self.message.as_ref().map(ToString::to_string));
+        parts.extend(
+            self.fields
+                .iter()
+                .map(|(field, value)| format!("{field}={}", value_to_string(value))),
+        );
+        parts.extend(
+            self.span
+                .as_ref()
+                .map(|span| format!("in_span={}", serde_json::Value::from(span.as_str()))),
+        );
+        if parts.is_empty() {
+            f.write_str("any event")
+        } else {
+            write!(f, "`{}`", parts.join(" "))
+        }
+    }
+}
+
+/// Why a pattern didn't match, and where.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchError {
+    /// The path of the span that didn't match, see [`crate::Change`]
+    pub path: String,
+    pub reason: String,
+}
+
+impl Display for MatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.reason)
+    }
+}
+
+impl std::error::Error for MatchError {}
+
+/// Whether a span called `name` (`target::name`) matches the name `pattern`:
+/// either the whole name, or its last `::` separated segments.
+pub(crate) fn name_matches(pattern: &str, name: &str) -> bool {
+    name == pattern
+        || name
+            .strip_suffix(pattern)
+            .is_some_and(|prefix| prefix.ends_with("::"))
+}
+
+/// Whether `target` is `pattern`, or one of its submodules.
+pub(crate) fn target_matches(pattern: &str, target: &str) -> bool {
+    target == pattern
+        || target
+            .strip_prefix(pattern)
+            .is_some_and(|suffix| suffix.starts_with("::"))
+}
+
+/// Whether an actual value matches an expected one.
+///
+/// Expected strings also match debug and error values with the same contents.
+pub(crate) fn value_matches(expected: &RecordValue, actual: &RecordValue) -> bool {
+    match (expected, actual) {
+        (
+            RecordValue::Value(serde_json::Value::String(expected)),
+            RecordValue::Debug(actual) | RecordValue::Error(actual),
+        ) => expected == actual,
+        (expected, actual) => expected == actual,
+    }
+}
+
+/// The descendants of `span` depth first, with their path and whether they are direct children.
+pub(crate) fn descendants<'a>(span: &'a Span, path: &str) -> Vec<(String, &'a Span, bool)> {
+    fn walk<'a>(
+        span: &'a Span,
+        path: &str,
+        is_child: bool,
+        out: &mut Vec<(String, &'a Span, bool)>,
+    ) {
+        for (child_path, child) in children_paths(span, path) {
+            out.push((child_path.clone(), child, is_child));
+            walk(child, &child_path, false, out);
+        }
+    }
+    let mut out = Vec::new();
+    walk(span, path, true, &mut out);
+    out
+}
+
+// Picks a distinct candidate for each pattern, in increasing order if `ordered`.
+fn assign(candidates: &[Vec<usize>], ordered: bool) -> bool {
+    if ordered {
+        // taking the earliest candidate after the previous pick leaves the most room to the next patterns
+        let mut last = None;
+        return candidates.iter().all(|candidates| {
+            let next = candidates
+                .iter()
+                .filter(|&&candidate| last.is_none_or(|last| candidate > last))
+                .min();
+            last = next.copied();
+            next.is_some()
+        });
+    }
+
+    // Kuhn's algorithm: each pattern takes a candidate, moving the patterns that held it along augmenting paths
+    fn augment(
+        pattern: usize,
+        candidates: &[Vec<usize>],
+        owners: &mut HashMap<usize, usize>,
+        visited: &mut HashSet<usize>,
+    ) -> bool {
+        for &candidate in &candidates[pattern] {
+            if !visited.insert(candidate) {
+                continue;
+            }
+            let free = match owners.get(&candidate) {
+                Some(&owner) => augment(owner, candidates, owners, visited),
+                None => true,
+            };
+            if free {
+                owners.insert(candidate, pattern);
+                return true;
+            }
+        }
+        false
+    }
+
+    let mut owners = HashMap::new();
+    (0..candidates.len())
+        .all(|pattern| augment(pattern, candidates, &mut owners, &mut HashSet::new()))
+}
+
+/// Asserts that a span tree contains a [`SpanPattern`],
+/// and prints why it doesn't along with the tree otherwise.
+#[macro_export]
+macro_rules! assert_spans_match {
+    ($spans:expr, $pattern:expr $(,)?) => {
+        match (&$spans, &$pattern) {
+            (spans, pattern) => {
+                if let Err(error) = $crate::SpanPattern::matches(pattern, spans) {
+                    panic!("span tree doesn't match the pattern: {}\n{}", error, spans);
+                }
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TREE: &str = r#"tests::root (INFO)
+  tests::do_stuff (INFO) number=52
+    - INFO "here i am!"
+    my_crate::debug (DEBUG)
+      my_crate::do_stuff2 (INFO) number=42
+        - INFO "here i am again!" retries=3
+  tests::do_stuff (INFO) number=53
+    tests::cleanup (INFO)
+"#;
+
+    fn tree() -> Span {
+        TREE.parse().unwrap()
+    }
+
+    #[test]
+    fn patterns_ignore_extra_spans_fields_and_events() {
+        let pattern = SpanPattern::new("root").child(
+            SpanPattern::new("do_stuff").descendant(
+                SpanPattern::new("my_crate::do_stuff2")
+                    .field("number", 42)
+                    .event(EventPattern::new().level(Level::INFO).field("retries", 3)),
+            ),
+        );
+        assert_eq!(Ok(()), pattern.matches(&tree()));
+
+        // the debug span sits between do_stuff and do_stuff2
+        let pattern = SpanPattern::new("root")
+            .child(SpanPattern::new("do_stuff").child(SpanPattern::new("do_stuff2")));
+        assert_eq!(
+            "tests::root/tests::do_stuff[0]: no child span matches `do_stuff2`",
+            pattern.matches(&tree()).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn patterns_can_be_ordered() {
+        let unordered = SpanPattern::any()
+            .child(SpanPattern::new("do_stuff").field("number", 53))
+            .child(SpanPattern::new("do_stuff").field("number", 52));
+        assert_eq!(Ok(()), unordered.matches(&tree()));
+        assert!(unordered.clone().ordered().matches(&tree()).is_err());
+
+        let twice = SpanPattern::any()
+            .descendant(SpanPattern::new("cleanup"))
+            .descendant(SpanPattern::new("cleanup"));
+        assert!(twice.matches(&tree()).is_err());
+    }
+
+    #[test]
+    fn many_similar_siblings_are_assigned_quickly() {
+        // a backtracking search tries every permutation before giving up here
+        let candidates: Vec<Vec<usize>> = (0..40).map(|_| (0..39).collect()).collect();
+        assert!(!assign(&candidates, false));
+        assert!(!assign(&candidates, true));
+        assert!(assign(&candidates[1..], false));
+        assert!(assign(&candidates[1..], true));
+
+        // the first pattern has to give its candidate up to the second one
+        assert!(assign(&[vec![0, 1], vec![0]], false));
+        assert!(!assign(&[vec![0, 1], vec![0]], true));
+        assert!(assign(&[vec![0, 2], vec![1]], true));
+    }
+
+    #[test]
+    fn mismatches_point_at_the_closest_span() {
+        let pattern = SpanPattern::new("root").descendant(
+            SpanPattern::new("do_stuff2")
+                .field("number", 43)
+                .event(EventPattern::new().message("here i am!")),
+        );
+        assert_eq!(
+            "tests::root/tests::do_stuff[0]/my_crate::debug/my_crate::do_stuff2: expected number=43, found 42",
+            pattern.matches(&tree()).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn a_span_matches_its_own_pattern() {
+        let span = tree();
+        assert_eq!(Ok(()), SpanPattern::from(&span).matches(&span));
+        assert_spans_match!(span, SpanPattern::from(&tree()));
+    }
+
+    #[test]
+    fn names_match_on_segments() {
+        assert!(name_matches("do_stuff", "my_crate::do_stuff"));
+        assert!(name_matches("my_crate::do_stuff", "my_crate::do_stuff"));
+        assert!(!name_matches("stuff", "my_crate::do_stuff"));
+        assert!(target_matches("my_crate", "my_crate::db"));
+        assert!(!target_matches("my_crate", "my_crate_2"));
+    }
+}
@@ -0,0 +1,137 @@
+//! Happens-before assertions.
+//!
+//! The layer stamps span opens, span closes and events with a global sequence number,
+//! which orders them even when they come from different threads.
+use crate::matcher::name_matches;
+use crate::report::span_name;
+use crate::{EventPattern, Filter, LogEvent, Report};
+use std::fmt::{self, Display};
+use tracing::Level;
+
+/// Something that happens while a test runs, which `assert_before!` can order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Moment {
+    /// A span called `name` was created.
+    Opened(String),
+    /// A span called `name` was closed.
+    Closed(String),
+    /// An event matching the pattern was emitted.
+    Event(EventPattern),
+}
+
+/// The moments a span goes through, see [`span`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpanMoments {
+    pub opened: Moment,
+    pub closed: Moment,
+}
+
+/// The moments the spans called `name` were opened and closed.
+///
+/// Names match like they do in [`crate::SpanPattern::new`].
+pub fn span(name: impl Into<String>) -> SpanMoments {
+    let name = name.into();
+    SpanMoments {
+        opened: Moment::Opened(name.clone()),
+        closed: Moment::Closed(name),
+    }
+}
+
+/// The moments events matching `pattern` were emitted.
+///
+/// A string matches the event message.
+pub fn event(pattern: impl Into<EventPattern>) -> Moment {
+    Moment::Event(pattern.into())
+}
+
+impl Display for Moment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Moment::Opened(name) => write!(f, "`{name}` opened"),
+            Moment::Closed(name) => write!(f, "`{name}` closed"),
+            Moment::Event(pattern) => write!(f, "event {pattern}"),
+        }
+    }
+}
+
+impl Report {
+    /// The sequence numbers of each time `moment` happened, in increasing order.
+    ///
+    /// Levels aren't filtered: every span and event under the root is considered.
+    pub fn sequence_numbers(&self, moment: &Moment) -> Vec<u64> {
+        let mut sequence_numbers: Vec<u64> = match moment {
+            Moment::Opened(name) | Moment::Closed(name) => self
+                .spans_under_root()
+                .map(|(_, recorder)| recorder)
+                .filter(|recorder| {
+                    recorder
+                        .metadata()
+                        .is_some_and(|metadata| name_matches(name, &span_name(metadata)))
+                })
+                .filter_map(|recorder| match moment {
+                    Moment::Opened(_) => recorder.opened_sequence(),
+                    _ => recorder.closed_sequence(),
+                })
+                .collect(),
+            Moment::Event(pattern) => {
+                let filter = Filter::new(Level::TRACE);
+                self.node_to_id
+                    .iter()
+                    .filter(|(&node, _)| self.is_under_root(node))
+                    .flat_map(|(&node, &span_id)| {
+                        let scope = self.span_scope(node);
+                        self.logs
+                            .events_for_span_id_and_filter(span_id, &filter)
+                            .filter(move |event| {
+                                pattern.matches(&LogEvent::from(*event).with_scope(scope.clone()))
+                            })
+                            .map(|event| event.sequence)
+                            .collect::<Vec<_>>()
+                    })
+                    .collect()
+            }
+        };
+        sequence_numbers.sort_unstable();
+        sequence_numbers
+    }
+}
+
+#[doc(hidden)]
+pub fn __check_before(report: &Report, before: &Moment, after: &Moment) -> Result<(), String> {
+    let first = report.sequence_numbers(before);
+    let then = report.sequence_numbers(after);
+    let (Some(last_before), Some(first_after)) = (first.last(), then.first()) else {
+        let missing = if first.is_empty() { before } else { after };
+        return Err(format!(
+            "expected {before} before {after}, but {missing} never happened"
+        ));
+    };
+
+    if last_before < first_after {
+        Ok(())
+    } else {
+        Err(format!(
+            "expected {before} before {after}, but {before} happened at #{last_before}, after {after} at #{first_after}"
+        ))
+    }
+}
+
+/// Asserts every time `before` happened came before every time `after` happened.
+///
+/// `span` and `event` are in scope in the arguments, so they don't need to be imported,
+/// nor clash with `tracing`'s macros and the test's own functions.
+///
+/// ```ignore
+/// assert_before!(report, span("cache::get").closed, span("upstream::fetch").opened);
+/// assert_before!(report, span("db::commit").closed, event("audit log written"));
+/// ```
+#[macro_export]
+macro_rules! assert_before {
+    ($report:expr, $before:expr, $after:expr $(,)?) => {{
+        #[allow(unused_imports)]
+        use $crate::{event, span};
+        if let Err(error) = $crate::__check_before(&$report, &$before, &$after) {
+            panic!("{}", error);
+        }
+    }};
+}
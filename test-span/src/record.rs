@@ -3,8 +3,11 @@ use ::tracing::{
     span,
 };
 use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
 
 use crate::attribute::OwnedMetadata;
+use crate::enters::MisplacedExit;
+use crate::layer::next_sequence;
 
 type FieldName = String;
 
@@ -16,6 +19,12 @@ pub enum RecordValue {
     Debug(String),
 }
 
+impl RecordValue {
+    pub(crate) fn value(value: impl Into<serde_json::Value>) -> Self {
+        RecordValue::Value(value.into())
+    }
+}
+
 #[derive(Default, Clone, Debug)]
 pub(crate) struct RecordEverything(Vec<Record>);
 
@@ -86,6 +95,15 @@ pub type Record = (FieldName, RecordValue);
 pub struct Recorder {
     metadata: Option<OwnedMetadata>,
     visitor: RecordEverything,
+    opened_at: Option<SystemTime>,
+    closed_at: Option<SystemTime>,
+    opened_sequence: Option<u64>,
+    closed_sequence: Option<u64>,
+    first_entered: Option<(u64, SystemTime)>,
+    last_entered: Option<SystemTime>,
+    last_exited: Option<(u64, SystemTime)>,
+    entered: usize,
+    misplaced_exits: Vec<(u64, MisplacedExit)>,
 }
 
 impl Recorder {
@@ -93,9 +111,83 @@ impl Recorder {
         let mut owned_metadata: OwnedMetadata = attributes.metadata().into();
         owned_metadata.span_id = Some(span_id.into_u64());
         self.metadata = Some(owned_metadata);
+        self.opened_at = Some(SystemTime::now());
+        self.opened_sequence = Some(next_sequence());
         attributes.record(&mut self.visitor)
     }
 
+    pub fn close(&mut self) {
+        self.closed_at = Some(SystemTime::now());
+        self.closed_sequence = Some(next_sequence());
+    }
+
+    pub fn enter(&mut self) {
+        if self.first_entered.is_none() {
+            self.first_entered = Some((next_sequence(), SystemTime::now()));
+        }
+        self.last_entered = Some(SystemTime::now());
+        self.entered += 1;
+    }
+
+    pub fn exit(&mut self) {
+        self.last_exited = Some((next_sequence(), SystemTime::now()));
+        self.entered = self.entered.saturating_sub(1);
+    }
+
+    /// Keeps track of an exit that didn't match what its thread entered, right after `exit`
+    pub(crate) fn misplaced_exit(&mut self, misplaced: MisplacedExit) {
+        let sequence = self
+            .last_exited
+            .map(|(sequence, _)| sequence)
+            .unwrap_or_default();
+        self.misplaced_exits.push((sequence, misplaced));
+    }
+
+    /// The exits that didn't match what their thread entered, along with their sequence number
+    pub(crate) fn misplaced_exits(&self) -> &[(u64, MisplacedExit)] {
+        &self.misplaced_exits
+    }
+
+    /// The sequence number and time the span was first entered, `None` if it never was
+    pub fn first_entered(&self) -> Option<(u64, SystemTime)> {
+        self.first_entered
+    }
+
+    /// When the span was last entered, `None` if it never was
+    pub fn last_entered_at(&self) -> Option<SystemTime> {
+        self.last_entered
+    }
+
+    /// The sequence number and time the span was last exited,
+    /// `None` if it never was or if it is still entered
+    pub fn last_exited(&self) -> Option<(u64, SystemTime)> {
+        if self.entered > 0 {
+            None
+        } else {
+            self.last_exited
+        }
+    }
+
+    /// When the span was created
+    pub fn opened_at(&self) -> Option<SystemTime> {
+        self.opened_at
+    }
+
+    /// When the span was closed, `None` if it is still alive
+    pub fn closed_at(&self) -> Option<SystemTime> {
+        self.closed_at
+    }
+
+    /// Where the span creation falls in the global order of span opens, span closes and events
+    pub fn opened_sequence(&self) -> Option<u64> {
+        self.opened_sequence
+    }
+
+    /// Where the span closing falls in the global order, `None` if it is still alive
+    pub fn closed_sequence(&self) -> Option<u64> {
+        self.closed_sequence
+    }
+
     pub fn metadata(&self) -> Option<&OwnedMetadata> {
         self.metadata.as_ref()
     }
@@ -104,6 +196,29 @@ impl Recorder {
         record.record(&mut self.visitor)
     }
 
+    /// The values the span recorded, in the order it recorded them
+    pub(crate) fn recorded_fields(&self) -> impl Iterator<Item = &Record> {
+        self.visitor.contents()
+    }
+
+    /// The fields the span declares that never got a value, in declaration order
+    pub fn unrecorded_fields(&self) -> Vec<String> {
+        let Some(metadata) = &self.metadata else {
+            return Vec::new();
+        };
+        metadata
+            .fields
+            .names()
+            .filter(|name| {
+                !self
+                    .visitor
+                    .contents()
+                    .any(|(recorded, _)| recorded == name)
+            })
+            .map(str::to_string)
+            .collect()
+    }
+
     pub fn contents(&self, filter: &crate::Filter) -> RecordWithMetadata {
         let mut r = RecordWithMetadata::new(self.metadata.clone().unwrap());
 
@@ -146,6 +261,10 @@ impl RecordWithMetadata {
         self.entries.iter()
     }
 
+    pub(crate) fn entries_mut(&mut self) -> impl Iterator<Item = &mut Record> {
+        self.entries.iter_mut()
+    }
+
     pub fn push(&mut self, entry: Record) {
         self.entries.push(entry)
     }
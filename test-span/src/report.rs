@@ -10,13 +10,14 @@ use tracing::Level;
 
 use crate::attribute::OwnedMetadata;
 use crate::layer::{ALL_LOGS, ALL_SPANS, SPAN_ID_TO_ROOT_AND_NODE_INDEX};
-use crate::log::LogsRecorder;
+use crate::log::{LogEvent, LogsRecorder};
+use crate::matcher::name_matches;
 use crate::record::{Record, RecordValue, RecordWithMetadata, Recorder};
 use crate::LazyMutex;
 
 pub(crate) static ALL_DAGS: LazyMutex<IndexMap<u64, Dag<u64, ()>>> = Lazy::new(Default::default);
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Filter {
     default_level: Level,
     targets: HashMap<String, Level>,
@@ -42,7 +43,7 @@ impl Filter {
             .iter()
             .filter(|(key, _)| metadata.target.starts_with(key.as_str()))
             .collect::<Vec<_>>();
-        for_target.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()));
+        for_target.sort_by_key(|(key, _)| std::cmp::Reverse(key.len()));
 
         for_target
             .first()
@@ -57,7 +58,8 @@ impl Filter {
 
 /// A tree which is effectively a Tree containing all the spans
 ///
-/// It can't do much yet, except being Serialized, which comes in handy for snapshots.
+/// It can be Serialized, which comes in handy for snapshots,
+/// or rendered and parsed back with its `Display` and `FromStr` implementations.
 #[derive(Debug, Serialize, PartialEq, Eq)]
 pub struct Span {
     // the span id
@@ -66,13 +68,19 @@ pub struct Span {
     // the function name
     name: String,
     // the recorded variables and logs
-    record: RecordWithMetadata,
+    pub(crate) record: RecordWithMetadata,
+    // the recorded variables only
+    #[serde(skip)]
+    pub(crate) fields: Vec<Record>,
+    // the events emitted while the span was the current one
+    #[serde(skip)]
+    pub(crate) events: Vec<LogEvent>,
     // the node's children
-    children: LinkedHashMap<ChildKey, Span>,
+    pub(crate) children: LinkedHashMap<ChildKey, Span>,
 }
 
 #[derive(Default, Debug, Hash, PartialEq, Eq)]
-struct ChildKey(String, usize);
+pub(crate) struct ChildKey(pub(crate) String, pub(crate) usize);
 
 impl Serialize for ChildKey {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
@@ -89,27 +97,83 @@ impl Span {
         Self {
             name,
             id,
+            fields: record.entries().cloned().collect(),
             record,
+            events: Default::default(),
             children: Default::default(),
         }
     }
+
+    // Attach the span's events, `records` are appended to the span record.
+    pub(crate) fn with_events(mut self, events: Vec<LogEvent>, records: Vec<Record>) -> Self {
+        self.events = events;
+        self.record.append(records);
+        self
+    }
+
+    pub(crate) fn push_child(&mut self, key: ChildKey, child: Span) {
+        self.children.insert(key, child);
+    }
+
+    /// The span id
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The span name, formatted as `target::name`
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The span metadata, and the records of both the span and its events
+    pub fn record(&self) -> &RecordWithMetadata {
+        &self.record
+    }
+
+    /// The fields recorded on the span itself
+    pub fn fields(&self) -> impl Iterator<Item = &Record> {
+        self.fields.iter()
+    }
+
+    /// The events emitted while the span was the current one
+    pub fn events(&self) -> impl Iterator<Item = &LogEvent> {
+        self.events.iter()
+    }
+
+    /// The span's direct children, in creation order
+    pub fn children(&self) -> impl Iterator<Item = &Span> {
+        self.children.values()
+    }
 }
+
 pub struct Report {
-    root_index: NodeIndex,
-    root_id: u64,
-    dag: Dag<u64, (), DefaultIx>,
-    spans: IndexMap<u64, Recorder>,
-    logs: LogsRecorder,
-    node_to_id: IndexMap<NodeIndex, u64>,
+    pub(crate) root_index: NodeIndex,
+    pub(crate) root_id: u64,
+    pub(crate) dag: Dag<u64, (), DefaultIx>,
+    pub(crate) spans: IndexMap<u64, Recorder>,
+    pub(crate) logs: LogsRecorder,
+    pub(crate) node_to_id: IndexMap<NodeIndex, u64>,
 }
 
 impl Report {
     pub fn from_root(root_node: u64) -> Self {
-        let id_to_node = SPAN_ID_TO_ROOT_AND_NODE_INDEX.lock().unwrap().clone();
-        let (global_root, root_node_index) = id_to_node
-            .get(&root_node)
-            .copied()
-            .expect("couldn't find rood node");
+        // the dag is copied while the id map is locked, so every node in it has an id,
+        // and spans are recorded before they get an id, so every id has a recorder
+        let (id_to_node, dag) = {
+            let id_to_node = SPAN_ID_TO_ROOT_AND_NODE_INDEX.lock().unwrap();
+            let (global_root, _) = id_to_node
+                .get(&root_node)
+                .copied()
+                .expect("couldn't find rood node");
+            let dag = ALL_DAGS
+                .lock()
+                .unwrap()
+                .get(&global_root)
+                .expect("no dag for root")
+                .clone();
+            (id_to_node.clone(), dag)
+        };
+        let (global_root, root_node_index) = id_to_node[&root_node];
 
         let node_to_id: IndexMap<NodeIndex, u64> = id_to_node
             .into_iter()
@@ -127,13 +191,6 @@ impl Report {
             .collect();
         let logs = ALL_LOGS.lock().unwrap().for_spans(relevant_spans);
 
-        let dag = ALL_DAGS
-            .lock()
-            .unwrap()
-            .get(&global_root)
-            .expect("no dag for root")
-            .clone();
-
         Self {
             root_index: root_node_index,
             root_id: root_node,
@@ -153,10 +210,11 @@ impl Report {
             );
 
             let mut records: Vec<_> = contents.entries().cloned().collect();
+            let mut events = self.events(self.root_id, self.root_index, filter);
 
-            self.dfs_logs_insert(&mut records, self.root_index, filter);
+            self.dfs_logs_insert(&mut records, &mut events, self.root_index, filter);
 
-            Records::new(records)
+            Records::new(records).with_events(events)
         } else {
             Default::default()
         }
@@ -165,9 +223,12 @@ impl Report {
     pub fn spans(&self, filter: &Filter) -> Span {
         if let Some(recorder) = self.spans.get(&self.root_id) {
             let metadata = recorder.metadata().expect("recorder without metadata");
-            let span_name = format!("{}::{}", metadata.target, metadata.name);
-
-            let mut root_span = Span::from(span_name, self.root_id, recorder.contents(filter));
+            let mut root_span =
+                Span::from(span_name(metadata), self.root_id, recorder.contents(filter))
+                    .with_events(
+                        self.events(self.root_id, self.root_index, filter),
+                        Vec::new(),
+                    );
 
             self.dfs_span_insert(&mut root_span, self.root_index, filter);
 
@@ -177,22 +238,34 @@ impl Report {
         }
     }
 
-    fn dfs_logs_insert(&self, records: &mut Vec<Record>, current_node: NodeIndex, filter: &Filter) {
+    /// Whether a span called `name` was recorded under the root, regardless of levels.
+    ///
+    /// `name` is either the whole `target::name`, or its last segments.
+    pub fn contains_span(&self, name: impl AsRef<str>) -> bool {
+        self.spans_under_root()
+            .filter_map(|(_, recorder)| recorder.metadata())
+            .any(|metadata| name_matches(name.as_ref(), &span_name(metadata)))
+    }
+
+    fn dfs_logs_insert(
+        &self,
+        records: &mut Vec<Record>,
+        events: &mut Vec<LogEvent>,
+        current_node: NodeIndex,
+        filter: &Filter,
+    ) {
         for child_node in self.sorted_children(current_node) {
-            let child_id = self
-                .node_to_id
-                .get(&child_node)
-                .expect("couldn't find span id for node");
+            // spans that are still being recorded are skipped, like `render_in_flight` does
+            let Some((child_id, child_recorder)) = self.resolve(child_node) else {
+                continue;
+            };
 
-            let mut child_record = self
-                .spans
-                .get(child_id)
-                .expect("graph and hashmap are tied; qed")
-                .contents(filter);
+            let mut child_record = child_recorder.contents(filter);
 
             child_record.append(self.logs.record_for_span_id_and_filter(*child_id, filter));
             records.extend(child_record.entries().cloned());
-            self.dfs_logs_insert(records, child_node, filter);
+            events.extend(self.events(*child_id, child_node, filter));
+            self.dfs_logs_insert(records, events, child_node, filter);
         }
     }
 
@@ -200,22 +273,15 @@ impl Report {
         current_span.children = self
             .sorted_children(current_node)
             .flat_map(|child_node| {
-                let child_id = self
-                    .node_to_id
-                    .get(&child_node)
-                    .expect("couldn't find span id for node");
-                let child_recorder = self
-                    .spans
-                    .get(child_id)
-                    .expect("graph and hashmap are tied; qed");
-
-                let metadata = child_recorder
-                    .metadata()
-                    .expect("couldn't find metadata for child record");
-
-                let span_name = format!("{}::{}", metadata.target, metadata.name);
-                let mut contents = child_recorder.contents(filter);
-                contents.append(self.logs.record_for_span_id_and_filter(*child_id, filter));
+                let Some((child_id, child_recorder)) = self.resolve(child_node) else {
+                    return Vec::new();
+                };
+                let Some(metadata) = child_recorder.metadata() else {
+                    return Vec::new();
+                };
+
+                let span_name = span_name(metadata);
+                let contents = child_recorder.contents(filter);
 
                 if !filter.is_enabled(metadata) {
                     // We continue to fetch children spans with an enabled filter
@@ -227,7 +293,11 @@ impl Report {
                         .into_iter()
                         .collect::<Vec<(ChildKey, Span)>>()
                 } else {
-                    let mut child_span = Span::from(span_name.clone(), *child_id, contents);
+                    let mut child_span = Span::from(span_name.clone(), *child_id, contents)
+                        .with_events(
+                            self.events(*child_id, child_node, filter),
+                            self.logs.record_for_span_id_and_filter(*child_id, filter),
+                        );
                     self.dfs_span_insert(&mut child_span, child_node, filter);
 
                     vec![(ChildKey(span_name, child_node.index()), child_span)]
@@ -236,7 +306,63 @@ impl Report {
             .collect();
     }
 
-    fn sorted_children(&self, node: NodeIndex) -> impl Iterator<Item = NodeIndex> {
+    fn events(&self, span_id: u64, node: NodeIndex, filter: &Filter) -> Vec<LogEvent> {
+        let scope = self.span_scope(node);
+        self.logs
+            .events_for_span_id_and_filter(span_id, filter)
+            .map(|event| LogEvent::from(event).with_scope(scope.clone()))
+            .collect()
+    }
+
+    /// The names of the spans enclosing `node`, from the root down to its own.
+    pub(crate) fn span_scope(&self, node: NodeIndex) -> Vec<String> {
+        scope(&self.dag, node, |node| {
+            self.resolve(node)?.1.metadata().map(span_name)
+        })
+    }
+
+    /// The names of the spans enclosing `node`, from the root down to its own, separated by `/`.
+    pub(crate) fn span_path(&self, node: NodeIndex) -> String {
+        self.span_scope(node).join("/")
+    }
+
+    /// Whether `node` is the root or one of its descendants, rather than elsewhere in the root's dag.
+    pub(crate) fn is_under_root(&self, node: NodeIndex) -> bool {
+        let mut current = Some(node);
+        while let Some(node) = current {
+            if node == self.root_index {
+                return true;
+            }
+            current = self
+                .dag
+                .parents(node)
+                .iter(&self.dag)
+                .next()
+                .map(|(_, parent)| parent);
+        }
+        false
+    }
+
+    /// The spans under the root, the root included, in the order they were created.
+    pub(crate) fn spans_under_root(&self) -> impl Iterator<Item = (&u64, &Recorder)> {
+        let under_root: HashSet<u64> = self
+            .node_to_id
+            .iter()
+            .filter(|(node, _)| self.is_under_root(**node))
+            .map(|(_, id)| *id)
+            .collect();
+        self.spans
+            .iter()
+            .filter(move |(id, _)| under_root.contains(id))
+    }
+
+    /// The id and recorder of a node, if the report has both.
+    pub(crate) fn resolve(&self, node: NodeIndex) -> Option<(&u64, &Recorder)> {
+        let id = self.node_to_id.get(&node)?;
+        Some((id, self.spans.get(id)?))
+    }
+
+    pub(crate) fn sorted_children(&self, node: NodeIndex) -> impl Iterator<Item = NodeIndex> {
         let mut children = self
             .dag
             .children(node)
@@ -249,14 +375,92 @@ impl Report {
     }
 }
 
+pub(crate) fn span_name(metadata: &OwnedMetadata) -> String {
+    format!("{}::{}", metadata.target, metadata.name)
+}
+
+/// The names of the spans enclosing `node`, from the root of the `dag` down to `node` itself.
+pub(crate) fn scope(
+    dag: &Dag<u64, ()>,
+    node: NodeIndex,
+    name: impl Fn(NodeIndex) -> Option<String>,
+) -> Vec<String> {
+    let mut scope = Vec::new();
+    let mut current = Some(node);
+    while let Some(node) = current {
+        scope.extend(name(node));
+        current = dag.parents(node).iter(dag).next().map(|(_, parent)| parent);
+    }
+    scope.reverse();
+    scope
+}
+
+/// All events that match `filter`, whichever root they belong to.
+pub(crate) fn all_events(logs: &LogsRecorder, filter: &Filter) -> Vec<LogEvent> {
+    let id_to_node = SPAN_ID_TO_ROOT_AND_NODE_INDEX.lock().unwrap().clone();
+    let dags = ALL_DAGS.lock().unwrap();
+    let spans = ALL_SPANS.lock().unwrap();
+
+    logs.all_events_for_filter(filter)
+        .map(|event| {
+            let event_scope = event
+                .metadata
+                .span_id
+                .and_then(|span_id| id_to_node.get(&span_id))
+                .and_then(|(root, node)| Some((dags.get(root)?, *node)))
+                .map(|(dag, node)| {
+                    scope(dag, node, |node| {
+                        let id = dag.node_weight(node)?;
+                        spans.get(id)?.metadata().map(span_name)
+                    })
+                })
+                .unwrap_or_default();
+            LogEvent::from(event).with_scope(event_scope)
+        })
+        .collect()
+}
+
 /// A Vec of log entries.
-#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
-pub struct Records(Vec<Record>);
+///
+/// Records captured by test-span also keep track of the events they come from,
+/// which allows `assert_log!` to match on levels, targets and spans.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(transparent)]
+pub struct Records {
+    pub(crate) records: Vec<Record>,
+    #[serde(skip)]
+    pub(crate) events: Vec<LogEvent>,
+}
+
+impl PartialEq for Records {
+    fn eq(&self, other: &Self) -> bool {
+        self.records == other.records
+    }
+}
+
+impl Eq for Records {}
 
 impl Records {
     /// Create a Records from log entries
     pub fn new(records: Vec<Record>) -> Self {
-        Self(records)
+        Self {
+            records,
+            events: Vec::new(),
+        }
+    }
+
+    pub(crate) fn with_events(self, events: Vec<LogEvent>) -> Self {
+        Self { events, ..self }
+    }
+
+    /// The log entries, in the order they were recorded
+    pub fn entries(&self) -> impl Iterator<Item = &Record> {
+        self.records.iter()
+    }
+
+    /// The events the entries come from, empty if the Records weren't captured by test-span
+    pub fn events(&self) -> impl Iterator<Item = &LogEvent> {
+        self.events.iter()
     }
 
     /// check if log message has been stored with the given payload.
@@ -266,7 +470,7 @@ impl Records {
 
     /// check if log entry (this can be span attributes or log messages) has been stored with the given payload.
     pub fn contains_value(&self, field_name: impl AsRef<str>, lookup: RecordValue) -> bool {
-        self.0
+        self.records
             .iter()
             .any(|(field, value)| field.as_str() == field_name.as_ref() && value == &lookup)
     }
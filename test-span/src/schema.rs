@@ -0,0 +1,420 @@
+//! Field conventions for spans, checked against what a test recorded.
+//!
+//! ```text
+//! {
+//!   "spans": [
+//!     {
+//!       "name": "http.request",
+//!       "required": { "http.method": "string", "http.route": "string", "http.status_code": "integer" },
+//!       "forbidden": ["http.request.header.authorization"]
+//!     },
+//!     { "target": "my_crate::subgraph", "required": { "subgraph.name": "string" } }
+//!   ]
+//! }
+//! ```
+use crate::matcher::{name_matches, target_matches};
+use crate::record::RecordValue;
+use crate::report::span_name;
+use crate::{Location, Report};
+use linked_hash_map::LinkedHashMap;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+use std::path::Path;
+
+/// The type a field value must have.
+///
+/// Fields recorded with `?` or `%` are `debug`, and errors recorded as `&dyn Error` are `error`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    String,
+    Integer,
+    /// An integer or a float
+    Number,
+    Bool,
+    Debug,
+    Error,
+    Any,
+}
+
+impl FieldType {
+    pub fn matches(&self, value: &RecordValue) -> bool {
+        match (self, value) {
+            (FieldType::Any, _) => true,
+            (FieldType::String, RecordValue::Value(value)) => value.is_string(),
+            (FieldType::Integer, RecordValue::Value(value)) => value.is_i64() || value.is_u64(),
+            (FieldType::Number, RecordValue::Value(value)) => value.is_number(),
+            (FieldType::Bool, RecordValue::Value(value)) => value.is_boolean(),
+            (FieldType::Debug, RecordValue::Debug(_)) => true,
+            (FieldType::Error, RecordValue::Error(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FieldType::String => "string",
+            FieldType::Integer => "integer",
+            FieldType::Number => "number",
+            FieldType::Bool => "bool",
+            FieldType::Debug => "debug",
+            FieldType::Error => "error",
+            FieldType::Any => "any",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The fields of the spans with a given name, or in a given target.
+///
+/// ```ignore
+/// SpanSchema::named("http.request")
+///     .required("http.method", FieldType::String)
+///     .required("http.status_code", FieldType::Integer)
+///     .forbidden("http.request.header.authorization")
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SpanSchema {
+    /// Matches the whole span name (`target::name`), or its last `::` separated segments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    /// Matches the target and its submodules
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+    #[serde(default)]
+    required: LinkedHashMap<String, FieldType>,
+    #[serde(default)]
+    optional: LinkedHashMap<String, FieldType>,
+    #[serde(default)]
+    forbidden: Vec<String>,
+}
+
+impl SpanSchema {
+    /// The fields of the spans called `name`, the same way `SpanPattern::new` matches names.
+    pub fn named(name: impl Into<String>) -> Self {
+        Self {
+            name: Some(name.into()),
+            ..Default::default()
+        }
+    }
+
+    /// The fields of the spans in `target`, or one of its submodules.
+    pub fn in_target(target: impl Into<String>) -> Self {
+        Self {
+            target: Some(target.into()),
+            ..Default::default()
+        }
+    }
+
+    /// The span must record a value of type `ty` for `field` before it closes.
+    pub fn required(mut self, field: impl Into<String>, ty: FieldType) -> Self {
+        self.required.insert(field.into(), ty);
+        self
+    }
+
+    /// If the span records `field`, the value must be of type `ty`.
+    pub fn optional(mut self, field: impl Into<String>, ty: FieldType) -> Self {
+        self.optional.insert(field.into(), ty);
+        self
+    }
+
+    /// The span must not record `field`.
+    pub fn forbidden(mut self, field: impl Into<String>) -> Self {
+        self.forbidden.push(field.into());
+        self
+    }
+
+    fn applies_to(&self, name: &str, target: &str) -> bool {
+        self.name
+            .as_deref()
+            .is_none_or(|pattern| name_matches(pattern, name))
+            && self
+                .target
+                .as_deref()
+                .is_none_or(|pattern| target_matches(pattern, target))
+    }
+}
+
+/// A registry of `SpanSchema`s, declared in Rust or loaded from JSON.
+///
+/// A span has to abide by every schema that applies to it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Schema {
+    spans: Vec<SpanSchema>,
+}
+
+impl Schema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn span(mut self, schema: SpanSchema) -> Self {
+        self.spans.push(schema);
+        self
+    }
+
+    /// Fails on unknown keys, which are most likely typos,
+    /// and on span schemas with neither a `name` nor a `target`, which would apply to every span.
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        let schema: Self = serde_json::from_str(json)?;
+        if let Some(index) = schema
+            .spans
+            .iter()
+            .position(|span| span.name.is_none() && span.target.is_none())
+        {
+            return Err(serde::de::Error::custom(format!(
+                "span schema {index} has neither a `name` nor a `target`"
+            )));
+        }
+        Ok(schema)
+    }
+
+    pub fn from_json_file(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let json = std::fs::read_to_string(path)?;
+        Self::from_json(&json).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    /// The violations of the spans under the root of `report`, span by span in the order they were created.
+    ///
+    /// Spans that are still open can still record their required fields, so only closed spans can miss them.
+    pub fn validate(&self, report: &Report) -> Vec<SchemaViolation> {
+        let mut nodes: Vec<_> = report.node_to_id.iter().collect();
+        nodes.sort();
+
+        let mut violations = Vec::new();
+        for (node, id) in nodes
+            .into_iter()
+            .filter(|(node, _)| report.is_under_root(**node))
+        {
+            let Some((recorder, metadata)) = report
+                .spans
+                .get(id)
+                .and_then(|recorder| Some((recorder, recorder.metadata()?)))
+            else {
+                continue;
+            };
+            let name = span_name(metadata);
+            let schemas: Vec<_> = self
+                .spans
+                .iter()
+                .filter(|schema| schema.applies_to(&name, &metadata.target))
+                .collect();
+            if schemas.is_empty() {
+                continue;
+            }
+
+            // the last value recorded for a field is the one that counts
+            let mut recorded: LinkedHashMap<&str, &RecordValue> = LinkedHashMap::new();
+            for (field, value) in recorder.recorded_fields() {
+                recorded.insert(field, value);
+            }
+
+            let path = report.span_path(*node);
+            let mut violation = |field: &str, kind| {
+                violations.push(SchemaViolation {
+                    path: path.clone(),
+                    location: metadata.location(),
+                    field: field.to_string(),
+                    kind,
+                })
+            };
+            for schema in schemas {
+                for (field, ty) in &schema.required {
+                    match recorded.get(field.as_str()) {
+                        Some(value) if !ty.matches(value) => violation(
+                            field,
+                            ViolationKind::WrongType {
+                                expected: *ty,
+                                actual: (*value).clone(),
+                            },
+                        ),
+                        None if recorder.closed_sequence().is_some() => {
+                            violation(field, ViolationKind::Missing { expected: *ty })
+                        }
+                        _ => {}
+                    }
+                }
+                for (field, ty) in &schema.optional {
+                    match recorded.get(field.as_str()) {
+                        Some(value) if !ty.matches(value) => violation(
+                            field,
+                            ViolationKind::WrongType {
+                                expected: *ty,
+                                actual: (*value).clone(),
+                            },
+                        ),
+                        _ => {}
+                    }
+                }
+                for field in &schema.forbidden {
+                    if recorded.contains_key(field.as_str()) {
+                        violation(field, ViolationKind::Forbidden);
+                    }
+                }
+            }
+        }
+        violations
+    }
+}
+
+/// A field that doesn't abide by a `Schema`, see `Schema::validate`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaViolation {
+    /// Formatted like `UnclosedSpan::path`
+    pub path: String,
+    /// Where the span was created
+    pub location: Location,
+    pub field: String,
+    pub kind: ViolationKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// A required field the span closed without
+    Missing {
+        expected: FieldType,
+    },
+    WrongType {
+        expected: FieldType,
+        actual: RecordValue,
+    },
+    Forbidden,
+}
+
+impl Display for SchemaViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            path,
+            location,
+            field,
+            kind,
+        } = self;
+        let path = format!("{path} (created {location})");
+        match kind {
+            ViolationKind::Missing { expected } => {
+                write!(
+                    f,
+                    "{path}: `{field}` ({expected}) is required but was never recorded"
+                )
+            }
+            ViolationKind::WrongType { expected, actual } => write!(
+                f,
+                "{path}: `{field}` should be {expected}, got {}",
+                serde_json::to_string(actual).unwrap_or_default()
+            ),
+            ViolationKind::Forbidden => write!(f, "{path}: `{field}` is forbidden"),
+        }
+    }
+}
+
+#[doc(hidden)]
+pub fn __check_schema(report: &Report, schema: &Schema) -> Result<(), String> {
+    let violations = schema.validate(report);
+    if violations.is_empty() {
+        return Ok(());
+    }
+    let violations: Vec<_> = violations
+        .iter()
+        .map(|violation| format!("  {violation}"))
+        .collect();
+    Err(format!(
+        "{} field(s) don't abide by the schema:\n{}",
+        violations.len(),
+        violations.join("\n")
+    ))
+}
+
+/// Asserts the spans in a `Report` abide by a `Schema`, and lists the violations otherwise.
+///
+/// ```ignore
+/// let schema = Schema::from_json_file("tests/span_schema.json").unwrap();
+/// assert_schema!(get_report(), schema);
+/// ```
+#[macro_export]
+macro_rules! assert_schema {
+    ($report:expr, $schema:expr $(,)?) => {
+        if let Err(error) = $crate::__check_schema(&$report, &$schema) {
+            panic!("{}", error);
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_types_match_record_values() {
+        let string = RecordValue::value("GET");
+        let integer = RecordValue::value(200);
+        let float = RecordValue::value(0.5);
+        let debug = RecordValue::Debug("Some(1)".to_string());
+
+        assert!(FieldType::String.matches(&string));
+        assert!(!FieldType::String.matches(&debug));
+        assert!(FieldType::Integer.matches(&integer));
+        assert!(!FieldType::Integer.matches(&float));
+        assert!(FieldType::Number.matches(&integer));
+        assert!(FieldType::Number.matches(&float));
+        assert!(FieldType::Debug.matches(&debug));
+        assert!(FieldType::Any.matches(&debug));
+    }
+
+    #[test]
+    fn schemas_are_loaded_from_json() {
+        let schema = Schema::from_json(
+            r#"{
+                "spans": [
+                    {
+                        "name": "http.request",
+                        "required": { "http.method": "string", "http.status_code": "integer" },
+                        "forbidden": ["password"]
+                    },
+                    { "target": "my_crate::subgraph", "optional": { "subgraph.name": "string" } }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            Schema::new()
+                .span(
+                    SpanSchema::named("http.request")
+                        .required("http.method", FieldType::String)
+                        .required("http.status_code", FieldType::Integer)
+                        .forbidden("password")
+                )
+                .span(
+                    SpanSchema::in_target("my_crate::subgraph")
+                        .optional("subgraph.name", FieldType::String)
+                ),
+            schema
+        );
+        assert!(schema.spans[0].applies_to("my_crate::http::http.request", "my_crate::http"));
+        assert!(schema.spans[1].applies_to("my_crate::subgraph::fetch", "my_crate::subgraph"));
+        assert!(!schema.spans[1].applies_to("my_crate::http::fetch", "my_crate::http"));
+    }
+
+    #[test]
+    fn invalid_schemas_are_rejected() {
+        let typo =
+            Schema::from_json(r#"{ "spans": [{ "name": "http.request", "requried": {} }] }"#)
+                .unwrap_err();
+        assert!(typo.to_string().starts_with("unknown field `requried`"));
+
+        let unknown = Schema::from_json(r#"{ "spans": [], "version": 1 }"#).unwrap_err();
+        assert!(unknown.to_string().starts_with("unknown field `version`"));
+
+        let everything =
+            Schema::from_json(r#"{ "spans": [{ "name": "a" }, { "forbidden": ["password"] }] }"#)
+                .unwrap_err();
+        assert_eq!(
+            "span schema 1 has neither a `name` nor a `target`",
+            everything.to_string()
+        );
+    }
+}
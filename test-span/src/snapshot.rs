@@ -0,0 +1,265 @@
+//! Snapshots taken by `#[test_span(snapshot)]` and `#[snapshot(...)]` once the test body returns.
+use crate::diff::{align, Edit};
+use crate::record::Record;
+use crate::text::{event_to_string, value_to_string};
+use crate::{RecordValue, Records, Span};
+use serde::Serialize;
+use std::fmt::Display;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// What redacted values are replaced with.
+pub const REDACTED: &str = "[redacted]";
+
+/// The environment variable that makes `assert_golden` write golden files instead of comparing them.
+pub const UPDATE_SNAPSHOTS: &str = "UPDATE_SNAPSHOTS";
+
+/// How spans and logs are written down in snapshots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SnapshotFormat {
+    /// The serde representation, as `insta::assert_json_snapshot!` writes it
+    #[default]
+    Json,
+    /// The compact text format, see `Span`'s `Display` implementation
+    Text,
+}
+
+impl SnapshotFormat {
+    pub fn render(self, value: &(impl Serialize + Display)) -> String {
+        let mut rendered = match self {
+            SnapshotFormat::Json => {
+                serde_json::to_string_pretty(value).expect("spans and logs serialize to json; qed")
+            }
+            SnapshotFormat::Text => value.to_string(),
+        };
+        if !rendered.ends_with('\n') {
+            rendered.push('\n');
+        }
+        rendered
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            SnapshotFormat::Json => "json",
+            SnapshotFormat::Text => "txt",
+        }
+    }
+}
+
+/// How spans and logs are prepared before they are snapshotted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SnapshotOptions {
+    redactions: Vec<String>,
+    sorted: bool,
+}
+
+impl SnapshotOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the values recorded for `field`, by spans and events, with `[redacted]`.
+    pub fn redact(mut self, field: impl Into<String>) -> Self {
+        self.redactions.push(field.into());
+        self
+    }
+
+    /// Sort sibling spans and log entries, so tests that run things concurrently
+    /// get the same snapshot every time.
+    pub fn sorted(self) -> Self {
+        Self {
+            sorted: true,
+            ..self
+        }
+    }
+
+    pub fn spans(&self, mut span: Span) -> Span {
+        self.prepare_span(&mut span);
+        span
+    }
+
+    pub fn logs(&self, mut records: Records) -> Records {
+        records
+            .records
+            .iter_mut()
+            .for_each(|record| self.redact_record(record));
+        records
+            .events
+            .iter_mut()
+            .flat_map(|event| event.entries_mut())
+            .for_each(|record| self.redact_record(record));
+        if self.sorted {
+            records
+                .records
+                .sort_by_cached_key(|(field, value)| (field.clone(), value_to_string(value)));
+            records
+                .events
+                .sort_by_cached_key(|event| event_to_string(event, ""));
+        }
+        records
+    }
+
+    fn prepare_span(&self, span: &mut Span) {
+        span.record
+            .entries_mut()
+            .chain(span.fields.iter_mut())
+            .chain(span.events.iter_mut().flat_map(|event| event.entries_mut()))
+            .for_each(|record| self.redact_record(record));
+
+        let mut children: Vec<_> = std::mem::take(&mut span.children).into_iter().collect();
+        for (_, child) in children.iter_mut() {
+            self.prepare_span(child);
+        }
+        if self.sorted {
+            // children are prepared first, so identical subtrees render the same way
+            children.sort_by_cached_key(|(_, child)| (child.name().to_string(), child.to_string()));
+        }
+        span.children = children.into_iter().collect();
+    }
+
+    fn redact_record(&self, (field, value): &mut Record) {
+        if self.redactions.contains(field) {
+            *value = RecordValue::Value(REDACTED.into());
+        }
+    }
+}
+
+/// Compares `actual` to the golden file at `path`, and panics with a diff if they differ.
+///
+/// If the `UPDATE_SNAPSHOTS` environment variable is set (to anything but `0` or `false`),
+/// the golden file is written instead.
+#[track_caller]
+pub fn assert_golden(path: impl AsRef<Path>, actual: &str) {
+    let path = path.as_ref();
+    if std::env::var(UPDATE_SNAPSHOTS)
+        .is_ok_and(|update| !matches!(update.as_str(), "" | "0" | "false"))
+    {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .unwrap_or_else(|e| panic!("couldn't create {}: {e}", parent.display()));
+        }
+        std::fs::write(path, actual)
+            .unwrap_or_else(|e| panic!("couldn't write {}: {e}", path.display()));
+        return;
+    }
+
+    let expected = match std::fs::read_to_string(path) {
+        Ok(expected) => expected,
+        Err(e) if e.kind() == ErrorKind::NotFound => panic!(
+            "golden file {} doesn't exist, run the test with {UPDATE_SNAPSHOTS}=1 to create it",
+            path.display()
+        ),
+        Err(e) => panic!("couldn't read {}: {e}", path.display()),
+    };
+    if expected != actual {
+        panic!(
+            "{} doesn't match (-expected +actual):\n{}\nrun the test with {UPDATE_SNAPSHOTS}=1 to update it",
+            path.display(),
+            line_diff(&expected, actual)
+        );
+    }
+}
+
+#[doc(hidden)]
+pub fn __golden_path(
+    manifest_dir: &str,
+    dir: &str,
+    module_path: &str,
+    test_name: &str,
+    kind: &str,
+    format: SnapshotFormat,
+) -> PathBuf {
+    Path::new(manifest_dir).join(dir).join(format!(
+        "{}__{test_name}.{kind}.{}",
+        module_path.replace("::", "__"),
+        format.extension()
+    ))
+}
+
+fn line_diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    align(&expected, &actual, |l, r| l == r)
+        .into_iter()
+        .flat_map(|edit| match edit {
+            Edit::Same(l, _) => vec![format!("  {}", expected[l])],
+            Edit::Removed(l) => vec![format!("- {}", expected[l])],
+            Edit::Added(r) => vec![format!("+ {}", actual[r])],
+            Edit::Changed(l, r) => vec![format!("- {}", expected[l]), format!("+ {}", actual[r])],
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(feature = "insta")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __insta_snapshot {
+    (json, $value:ident) => {
+        $crate::reexports::insta::assert_json_snapshot!($value)
+    };
+    (text, $value:ident) => {
+        $crate::reexports::insta::assert_snapshot!($value.to_string())
+    };
+}
+
+#[cfg(not(feature = "insta"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __insta_snapshot {
+    ($format:ident, $value:ident) => {
+        compile_error!(
+            "#[snapshot] uses insta by default, enable the `insta` feature of test-span or use `backend = \"golden\"`"
+        )
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TREE: &str = r#"tests::root (INFO)
+  tests::fetch (INFO) subgraph="reviews" request_id=2
+    - INFO "fetched" request_id=2
+  tests::fetch (INFO) subgraph="accounts" request_id=1
+"#;
+
+    #[test]
+    fn options_redact_and_sort() {
+        let span: Span = TREE.parse().unwrap();
+        let options = SnapshotOptions::new().redact("request_id").sorted();
+        assert_eq!(
+            r#"tests::root (INFO)
+  tests::fetch (INFO) subgraph="accounts" request_id="[redacted]"
+  tests::fetch (INFO) subgraph="reviews" request_id="[redacted]"
+    - INFO "fetched" request_id="[redacted]"
+"#,
+            options.spans(span).to_string()
+        );
+    }
+
+    #[test]
+    fn golden_files_are_compared_and_updated() {
+        let path =
+            std::env::temp_dir().join(format!("test-span-golden-{}.txt", std::process::id()));
+        std::fs::write(&path, "a\nb\nc\n").unwrap();
+        assert_golden(&path, "a\nb\nc\n");
+
+        let mismatch = std::panic::catch_unwind(|| assert_golden(&path, "a\nB\nc\nd\n"));
+        let message = *mismatch.unwrap_err().downcast::<String>().unwrap();
+        assert!(message.contains("  a\n- b\n+ B\n  c\n+ d\n"), "{message}");
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            Path::new("/crate/tests/golden/tests__traced_span_tests__works.spans.txt"),
+            __golden_path(
+                "/crate",
+                "tests/golden",
+                "tests::traced_span_tests",
+                "works",
+                "spans",
+                SnapshotFormat::Text
+            )
+        );
+    }
+}
@@ -0,0 +1,143 @@
+//! Notifications about what the layer records, for tests that react while they run.
+use crate::attribute::OwnedMetadata;
+use crate::layer::{ALL_SPANS, SPAN_ID_TO_ROOT_AND_NODE_INDEX};
+use crate::record::{Record, RecordEverything};
+use crate::{Layer, LazyMutex, LogEvent, TestTelemetry};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tracing::field::Visit;
+use tracing::Id;
+
+static SUBSCRIBERS: LazyMutex<HashMap<u64, Vec<UnboundedSender<Notification>>>> =
+    Lazy::new(Default::default);
+
+/// Something the layer recorded under a root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Notification {
+    SpanOpened {
+        id: u64,
+        /// `target::name`
+        name: String,
+        fields: Vec<Record>,
+    },
+    FieldsRecorded {
+        id: u64,
+        name: String,
+        fields: Vec<Record>,
+    },
+    EventEmitted {
+        /// The span the event was emitted in
+        span_id: u64,
+        event: LogEvent,
+    },
+    SpanClosed {
+        id: u64,
+        name: String,
+    },
+}
+
+/// The notifications about one root, in the order they were recorded.
+///
+/// It ends once the root is closed.
+pub struct Subscription {
+    receiver: UnboundedReceiver<Notification>,
+}
+
+impl Subscription {
+    /// Waits for the next notification, `None` once the root is closed.
+    pub async fn next(&mut self) -> Option<Notification> {
+        self.receiver.recv().await
+    }
+
+    /// The underlying channel, which `tokio_stream::wrappers::UnboundedReceiverStream` turns into a `Stream`.
+    pub fn into_receiver(self) -> UnboundedReceiver<Notification> {
+        self.receiver
+    }
+}
+
+/// Blocks the thread until the next notification, so it can't be used from async code.
+impl Iterator for Subscription {
+    type Item = Notification;
+
+    fn next(&mut self) -> Option<Notification> {
+        self.receiver.blocking_recv()
+    }
+}
+
+impl Layer {
+    /// Subscribes to what is recorded under the root of the span `id` from now on.
+    ///
+    /// The subscription has already ended if that root is closed, or if `id` was never recorded.
+    pub fn subscribe(id: &Id) -> Subscription {
+        let (sender, receiver) = unbounded_channel();
+        // held while looking at the root, so it can't be closed before the sender is stored
+        let mut subscribers = SUBSCRIBERS.lock().unwrap();
+        let root = SPAN_ID_TO_ROOT_AND_NODE_INDEX
+            .lock()
+            .unwrap()
+            .get(&id.into_u64())
+            .map(|(root, _)| *root);
+        let open_root = root.filter(|root| {
+            ALL_SPANS
+                .lock()
+                .unwrap()
+                .get(root)
+                .is_some_and(|recorder| recorder.closed_at().is_none())
+        });
+        // otherwise the sender is dropped, which ends the subscription
+        if let Some(root) = open_root {
+            subscribers.entry(root).or_default().push(sender);
+        }
+        Subscription { receiver }
+    }
+}
+
+impl TestTelemetry {
+    /// Subscribes to what is recorded under the test's root from now on, see `Layer::subscribe`.
+    pub fn subscribe(&self) -> Subscription {
+        Layer::subscribe(self.root_id())
+    }
+}
+
+/// Sends the notification built by `notification` to the subscribers of the root of `span_id`.
+pub(crate) fn publish(span_id: u64, notification: impl FnOnce() -> Notification) {
+    let mut subscribers = SUBSCRIBERS.lock().unwrap();
+    if subscribers.is_empty() {
+        return;
+    }
+    let Some((root, _)) = SPAN_ID_TO_ROOT_AND_NODE_INDEX
+        .lock()
+        .unwrap()
+        .get(&span_id)
+        .copied()
+    else {
+        return;
+    };
+    let Some(senders) = subscribers.get_mut(&root) else {
+        return;
+    };
+
+    let notification = notification();
+    senders.retain(|sender| sender.send(notification.clone()).is_ok());
+    // closing the root ends the subscriptions
+    if matches!(notification, Notification::SpanClosed { id, .. } if id == root)
+        || senders.is_empty()
+    {
+        subscribers.remove(&root);
+    }
+}
+
+pub(crate) fn fields(record: impl FnOnce(&mut dyn Visit)) -> Vec<Record> {
+    let mut visitor = RecordEverything::default();
+    record(&mut visitor);
+    visitor.contents().cloned().collect()
+}
+
+pub(crate) fn event(span_id: u64, event: &tracing::Event<'_>) -> Notification {
+    let metadata = OwnedMetadata::from(event.metadata()).with_span_id(span_id);
+    Notification::EventEmitted {
+        span_id,
+        event: LogEvent::new(metadata, fields(|visitor| event.record(visitor))),
+    }
+}
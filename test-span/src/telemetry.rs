@@ -0,0 +1,98 @@
+//! The handle `#[test_span]` tests get to what they recorded.
+use crate::{Checkpoint, Filter, Records, Report, Span, ZipkinSpan};
+use tracing::Id;
+
+/// Everything recorded under a test's root span, seen through a `Filter`.
+///
+/// `#[test_span]` injects one as `telemetry`, with the filter set by `#[level]` and `#[target]`.
+/// `with_filter` looks at the same run at another verbosity level.
+#[derive(Debug, Clone)]
+pub struct TestTelemetry {
+    root_id: Id,
+    filter: Filter,
+    // the sequence numbers the telemetry is restricted to, see `since` and `between`
+    window: Option<(u64, u64)>,
+}
+
+impl TestTelemetry {
+    pub fn new(root_id: Id, filter: Filter) -> Self {
+        Self {
+            root_id,
+            filter,
+            window: None,
+        }
+    }
+
+    /// The telemetry of the `#[test_span]` test that is running, found through the current span.
+    #[track_caller]
+    pub fn current() -> Self {
+        crate::current::current_test()
+    }
+
+    pub fn root_id(&self) -> &Id {
+        &self.root_id
+    }
+
+    pub fn filter(&self) -> &Filter {
+        &self.filter
+    }
+
+    /// The same telemetry, seen through `filter` instead.
+    pub fn with_filter(&self, filter: Filter) -> Self {
+        Self {
+            filter,
+            ..self.clone()
+        }
+    }
+
+    /// The same telemetry, restricted to the spans opened and the events emitted after `mark`.
+    pub fn since(&self, mark: Checkpoint) -> Self {
+        Self {
+            window: Some((mark.sequence(), u64::MAX)),
+            ..self.clone()
+        }
+    }
+
+    /// The same telemetry, restricted to the spans opened and the events emitted between two marks.
+    pub fn between(&self, start: Checkpoint, end: Checkpoint) -> Self {
+        Self {
+            window: Some((start.sequence(), end.sequence())),
+            ..self.clone()
+        }
+    }
+
+    /// Returns a `Span`, a tree containing all the spans that are children of the root.
+    pub fn spans(&self) -> Span {
+        self.report().spans(&self.filter)
+    }
+
+    /// Returns the `Records` of the root and its children.
+    pub fn logs(&self) -> Records {
+        self.report().logs(&self.filter)
+    }
+
+    /// Returns both the output of `spans` and `logs`, from the same snapshot.
+    pub fn telemetry(&self) -> (Span, Records) {
+        let report = self.report();
+        (report.spans(&self.filter), report.logs(&self.filter))
+    }
+
+    /// Returns a `Report` over everything recorded under the root, regardless of levels.
+    pub fn report(&self) -> Report {
+        let report = Report::from_root(self.root_id.into_u64());
+        match self.window {
+            Some((after, before)) => report.window(after, before),
+            None => report,
+        }
+    }
+
+    /// Marks the current point of the run, see `since` and `between`.
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint::now()
+    }
+
+    /// Returns the spans as a Zipkin v2 span list.
+    pub fn zipkin(&self) -> Vec<ZipkinSpan> {
+        self.report().zipkin(&self.filter)
+    }
+}
@@ -0,0 +1,649 @@
+//! A compact, indented text format for [`Span`] trees and [`Records`].
+//!
+//! Each span is a line, with its level and fields inline.
+//! Its events are nested below it, prefixed with `-`, followed by its children:
+//!
+//! ```text
+//! tests::root (INFO)
+//!   tests::do_stuff (INFO) number=52
+//!     - INFO "here i am!"
+//!     - WARN my_crate::db: "timed out" retries=3
+//!     my_crate::do_stuff2 (DEBUG)
+//! ```
+//!
+//! Values use the JSON syntax. Debug values are prefixed with `?`, and errors with `!`.
+//! An event message is written right after the event level, its target is only written
+//! when it differs from the target of the span it belongs to.
+//! A span name that contains `::` itself is quoted after its target: `my_crate::db::"pool::acquire"`.
+//!
+//! Span names, targets, levels, fields and events survive a round trip.
+//! Span ids, source locations and module paths don't.
+use std::fmt::{self, Display, Write};
+use std::str::FromStr;
+
+use tracing::Level;
+
+use crate::attribute::{OwnedFieldSet, OwnedMetadata};
+use crate::log::LogEvent;
+use crate::record::{Record, RecordValue, RecordWithMetadata};
+use crate::report::{ChildKey, Records, Span};
+
+const INDENT: &str = "  ";
+
+/// The error returned when a text snapshot can't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// The line the error occurred at, starting at 1
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_span(f, self, 0)
+    }
+}
+
+impl Display for Records {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for record in self.entries() {
+            write_record(f, record)?;
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_span(f: &mut impl Write, span: &Span, depth: usize) -> fmt::Result {
+    let metadata = span.record().metadata();
+    f.write_str(&INDENT.repeat(depth))?;
+    write_span_name(f, span.name(), &metadata)?;
+    if !metadata.level.is_empty() {
+        write!(f, " ({})", metadata.level)?;
+    }
+    for record in span.fields() {
+        f.write_char(' ')?;
+        write_record(f, record)?;
+    }
+    writeln!(f)?;
+
+    for event in span.events() {
+        write!(f, "{}- ", INDENT.repeat(depth + 1))?;
+        write_event(f, event, &metadata.target)?;
+        writeln!(f)?;
+    }
+
+    for child in span.children() {
+        write_span(f, child, depth + 1)?;
+    }
+    Ok(())
+}
+
+fn write_event(f: &mut impl Write, event: &LogEvent, span_target: &str) -> fmt::Result {
+    f.write_str(&event.metadata().level)?;
+    if event.metadata().target != span_target {
+        write!(f, " {}:", event.metadata().target)?;
+    }
+    for (index, record) in event.entries().enumerate() {
+        f.write_char(' ')?;
+        match record {
+            (field, RecordValue::Debug(message)) if index == 0 && field == "message" => {
+                write_string(f, message)?
+            }
+            record => write_record(f, record)?,
+        }
+    }
+    Ok(())
+}
+
+/// Renders an event the way it appears in a text snapshot, without its `-` prefix.
+pub(crate) fn event_to_string(event: &LogEvent, span_target: &str) -> String {
+    let mut s = String::new();
+    write_event(&mut s, event, span_target).expect("writing to a String can't fail; qed");
+    s
+}
+
+/// Renders a value the way it appears in a text snapshot.
+pub(crate) fn value_to_string(value: &RecordValue) -> String {
+    let mut s = String::new();
+    write_value(&mut s, value).expect("writing to a String can't fail; qed");
+    s
+}
+
+fn write_record(f: &mut impl Write, (field, value): &Record) -> fmt::Result {
+    write_word(f, field)?;
+    f.write_char('=')?;
+    write_value(f, value)
+}
+
+fn write_value(f: &mut impl Write, value: &RecordValue) -> fmt::Result {
+    match value {
+        RecordValue::Value(value) => f.write_str(&value.to_string()),
+        RecordValue::Debug(value) => {
+            f.write_char('?')?;
+            write_string(f, value)
+        }
+        RecordValue::Error(value) => {
+            f.write_char('!')?;
+            write_string(f, value)
+        }
+    }
+}
+
+// Names and field names are written as is, unless they would be ambiguous.
+fn write_word(f: &mut impl Write, word: &str) -> fmt::Result {
+    let ambiguous = word.is_empty()
+        || word.starts_with(['"', '-', '('])
+        || word.contains(|c: char| c.is_whitespace() || c == '=');
+    if ambiguous {
+        write_string(f, word)
+    } else {
+        f.write_str(word)
+    }
+}
+
+// `target::name`, with the name quoted if it contains `::` itself, so that the parser splits it at the same place.
+fn write_span_name(f: &mut impl Write, full_name: &str, metadata: &OwnedMetadata) -> fmt::Result {
+    let split = !metadata.target.is_empty()
+        && full_name
+            .strip_prefix(metadata.target.as_str())
+            .and_then(|rest| rest.strip_prefix("::"))
+            == Some(metadata.name.as_str());
+    if !split {
+        return if full_name.contains("::") && metadata.target.is_empty() {
+            write_string(f, full_name)
+        } else {
+            write_word(f, full_name)
+        };
+    }
+    let name = &metadata.name;
+    let ambiguous = metadata
+        .target
+        .contains(|c: char| c.is_whitespace() || c == '=' || c == '"')
+        || metadata.target.starts_with(['-', '(']);
+    if ambiguous {
+        write_string(f, &metadata.target)?;
+    } else {
+        f.write_str(&metadata.target)?;
+    }
+    f.write_str("::")?;
+    if name.contains("::") || name.contains('"') {
+        write_string(f, name)
+    } else {
+        write_word(f, name)
+    }
+}
+
+fn write_string(f: &mut impl Write, s: &str) -> fmt::Result {
+    f.write_str(&serde_json::Value::from(s).to_string())
+}
+
+impl FromStr for Span {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = SpanParser::default();
+        for (index, line) in s.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            parser.line(line).map_err(|message| ParseError {
+                line: index + 1,
+                message,
+            })?;
+        }
+        parser.finish().ok_or_else(|| ParseError {
+            line: 0,
+            message: "no span found".to_string(),
+        })
+    }
+}
+
+impl FromStr for Records {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                parse_record_line(line).map_err(|message| ParseError {
+                    line: index + 1,
+                    message,
+                })
+            })
+            .collect::<Result<_, _>>()
+            .map(Records::new)
+    }
+}
+
+fn parse_record_line(line: &str) -> Result<Record, String> {
+    let mut cursor = Cursor(line.trim());
+    let record = cursor.record()?;
+    cursor.end()?;
+    Ok(record)
+}
+
+// A span being parsed, its children and events are attached once it is complete.
+struct PendingSpan {
+    span: Span,
+    events: Vec<LogEvent>,
+}
+
+#[derive(Default)]
+struct SpanParser {
+    // stack[depth] is the last span seen at this depth
+    stack: Vec<PendingSpan>,
+    root: Option<Span>,
+    next_id: u64,
+}
+
+impl SpanParser {
+    fn line(&mut self, line: &str) -> Result<(), String> {
+        let content = line.trim_start_matches(' ');
+        let indent = line.len() - content.len();
+        if indent % INDENT.len() != 0 {
+            return Err(format!(
+                "indentation must be a multiple of {} spaces",
+                INDENT.len()
+            ));
+        }
+        let depth = indent / INDENT.len();
+        let mut cursor = Cursor(content.trim_end());
+
+        if cursor.eat("- ") {
+            if depth == 0 || depth > self.stack.len() {
+                return Err("events must be nested in a span".to_string());
+            }
+            self.unwind(depth);
+            let scope = self
+                .stack
+                .iter()
+                .map(|pending| pending.span.name().to_string())
+                .collect();
+            let parent = self.stack.last_mut().expect("depth is at least 1; qed");
+            let event = cursor.event(&parent.span)?.with_scope(scope);
+            parent.events.push(event);
+        } else {
+            if depth > self.stack.len() {
+                return Err("unexpected indentation".to_string());
+            }
+            if depth == 0 && (self.root.is_some() || !self.stack.is_empty()) {
+                return Err("there can only be one root span".to_string());
+            }
+            self.unwind(depth);
+            self.next_id += 1;
+            let span = cursor.span(self.next_id)?;
+            self.stack.push(PendingSpan {
+                span,
+                events: Vec::new(),
+            });
+        }
+        Ok(())
+    }
+
+    // Complete the spans that are deeper than `depth`
+    fn unwind(&mut self, depth: usize) {
+        while self.stack.len() > depth {
+            let PendingSpan { span, events } = self.stack.pop().expect("stack isn't empty; qed");
+            // like captured spans, the root record doesn't contain its events' records
+            let records = if self.stack.is_empty() {
+                Vec::new()
+            } else {
+                events.iter().flat_map(|e| e.entries().cloned()).collect()
+            };
+            let span = span.with_events(events, records);
+
+            match self.stack.last_mut() {
+                Some(parent) => {
+                    let key = ChildKey(span.name().to_string(), span.id() as usize);
+                    parent.span.push_child(key, span);
+                }
+                None => self.root = Some(span),
+            }
+        }
+    }
+
+    fn finish(mut self) -> Option<Span> {
+        self.unwind(0);
+        self.root
+    }
+}
+
+struct Cursor<'a>(&'a str);
+
+impl Cursor<'_> {
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn eat(&mut self, prefix: &str) -> bool {
+        match self.0.strip_prefix(prefix) {
+            Some(rest) => {
+                self.0 = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn end(&self) -> Result<(), String> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("unexpected `{}`", self.0))
+        }
+    }
+
+    fn skip_space(&mut self) {
+        self.0 = self.0.trim_start();
+    }
+
+    fn span(&mut self, id: u64) -> Result<Span, String> {
+        let (target, short_name) = self.span_name()?;
+        let name = if target.is_empty() {
+            short_name.clone()
+        } else {
+            format!("{target}::{short_name}")
+        };
+        self.skip_space();
+
+        let mut level = String::new();
+        if self.eat("(") {
+            let (inner, rest) = self
+                .0
+                .split_once(')')
+                .ok_or_else(|| "unclosed level".to_string())?;
+            level = parse_level(inner)?;
+            self.0 = rest;
+        }
+
+        let fields = self.records()?;
+        let metadata = OwnedMetadata {
+            name: short_name,
+            target,
+            level,
+            fields: field_set(&fields),
+            span_id: Some(id),
+            ..Default::default()
+        };
+
+        let mut record = RecordWithMetadata::new(metadata);
+        record.append(fields);
+        Ok(Span::from(name, id, record))
+    }
+
+    fn event(&mut self, parent: &Span) -> Result<LogEvent, String> {
+        let (level, rest) = self.0.split_once(' ').unwrap_or((self.0, ""));
+        let level = parse_level(level)?;
+        self.0 = rest;
+        self.skip_space();
+
+        let mut target = parent.record().metadata().target;
+        if let Some((word, rest)) = self.0.split_once(' ').or(Some((self.0, ""))) {
+            if let Some(explicit_target) = word.strip_suffix(':') {
+                if !explicit_target.contains(['=', '"']) {
+                    target = explicit_target.to_string();
+                    self.0 = rest;
+                    self.skip_space();
+                }
+            }
+        }
+
+        let mut entries = Vec::new();
+        if self.0.starts_with('"') {
+            let mut lookahead = Cursor(self.0);
+            let message = lookahead.string()?;
+            if !lookahead.0.starts_with('=') {
+                entries.push(("message".to_string(), RecordValue::Debug(message)));
+                self.0 = lookahead.0;
+            }
+        }
+        entries.extend(self.records()?);
+
+        let metadata = OwnedMetadata {
+            name: "event".to_string(),
+            target,
+            level,
+            fields: field_set(&entries),
+            span_id: Some(parent.id()),
+            ..Default::default()
+        };
+        Ok(LogEvent::new(metadata, entries))
+    }
+
+    fn records(&mut self) -> Result<Vec<Record>, String> {
+        let mut records = Vec::new();
+        self.skip_space();
+        while !self.is_empty() {
+            records.push(self.record()?);
+            self.skip_space();
+        }
+        Ok(records)
+    }
+
+    fn record(&mut self) -> Result<Record, String> {
+        let field = self.word()?;
+        if !self.eat("=") {
+            return Err(format!("expected `=` after `{field}`"));
+        }
+        let value = if self.eat("?") {
+            RecordValue::Debug(self.string()?)
+        } else if self.eat("!") {
+            RecordValue::Error(self.string()?)
+        } else {
+            RecordValue::Value(self.json()?)
+        };
+        Ok((field, value))
+    }
+
+    // The target and the name of a span, see `write_span_name`
+    fn span_name(&mut self) -> Result<(String, String), String> {
+        let target = if self.0.starts_with('"') {
+            let quoted = self.string_prefix()?;
+            if !self.eat("::") {
+                // a name that couldn't be split, it is taken as a whole
+                return Ok((String::new(), quoted));
+            }
+            quoted
+        } else {
+            let end = self
+                .0
+                .find(|c: char| c.is_whitespace() || c == '=' || c == '"')
+                .unwrap_or(self.0.len());
+            if end == 0 {
+                return Err(format!("expected a name, found `{}`", self.0));
+            }
+            let (word, rest) = self.0.split_at(end);
+            self.0 = rest;
+            match word.strip_suffix("::") {
+                // the name is quoted
+                Some(target) if self.0.starts_with('"') => target.to_string(),
+                _ => {
+                    if !(self.is_empty() || self.0.starts_with(char::is_whitespace)) {
+                        return Err(format!("unexpected `{}`", self.0));
+                    }
+                    return Ok(match word.rsplit_once("::") {
+                        Some((target, name)) => (target.to_string(), name.to_string()),
+                        None => (String::new(), word.to_string()),
+                    });
+                }
+            }
+        };
+        let name = if self.0.starts_with('"') {
+            self.string()?
+        } else {
+            self.word()?
+        };
+        Ok((target, name))
+    }
+
+    // A JSON string that may be directly followed by something else, such as `::`
+    fn string_prefix(&mut self) -> Result<String, String> {
+        let mut values =
+            serde_json::Deserializer::from_str(self.0).into_iter::<serde_json::Value>();
+        match values.next() {
+            Some(Ok(serde_json::Value::String(s))) => {
+                self.0 = &self.0[values.byte_offset()..];
+                Ok(s)
+            }
+            Some(Ok(other)) => Err(format!("expected a string, found `{other}`")),
+            Some(Err(e)) => Err(format!("invalid value `{}`: {e}", self.0)),
+            None => Err("expected a value".to_string()),
+        }
+    }
+
+    fn word(&mut self) -> Result<String, String> {
+        if self.0.starts_with('"') {
+            return self.string();
+        }
+        let end = self
+            .0
+            .find(|c: char| c.is_whitespace() || c == '=')
+            .unwrap_or(self.0.len());
+        if end == 0 {
+            return Err(format!("expected a name, found `{}`", self.0));
+        }
+        let (word, rest) = self.0.split_at(end);
+        self.0 = rest;
+        Ok(word.to_string())
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        match self.json()? {
+            serde_json::Value::String(s) => Ok(s),
+            other => Err(format!("expected a string, found `{other}`")),
+        }
+    }
+
+    fn json(&mut self) -> Result<serde_json::Value, String> {
+        let mut values =
+            serde_json::Deserializer::from_str(self.0).into_iter::<serde_json::Value>();
+        let value = match values.next() {
+            Some(Ok(value)) => value,
+            Some(Err(e)) => return Err(format!("invalid value `{}`: {e}", self.0)),
+            None => return Err("expected a value".to_string()),
+        };
+        self.0 = &self.0[values.byte_offset()..];
+        if !(self.is_empty() || self.0.starts_with(char::is_whitespace)) {
+            return Err(format!("unexpected `{}`", self.0));
+        }
+        Ok(value)
+    }
+}
+
+fn parse_level(level: &str) -> Result<String, String> {
+    match level {
+        "TRACE" | "DEBUG" | "INFO" | "WARN" | "ERROR" => Ok(level
+            .parse::<Level>()
+            .expect("level is valid; qed")
+            .to_string()),
+        other => Err(format!("unknown level `{other}`")),
+    }
+}
+
+fn field_set(records: &[Record]) -> OwnedFieldSet {
+    let mut names: Vec<String> = Vec::new();
+    for (field, _) in records {
+        if !names.contains(field) {
+            names.push(field.clone());
+        }
+    }
+    OwnedFieldSet::new(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TREE: &str = r#"tests::root (INFO)
+  - INFO "in the root"
+  tests::do_stuff (INFO) number=52 name="with spaces"
+    - INFO "here i am!"
+    - WARN my_crate::db: "timed out" retries=3 error=!"boom"
+    my_crate::do_stuff2 (DEBUG) status=?"Some(200)"
+      - DEBUG tests: "debug: here i am again!"
+  tests::do_stuff (INFO) number=52 name="with spaces"
+"#;
+
+    #[test]
+    fn text_round_trip() {
+        let span: Span = TREE.parse().unwrap();
+        assert_eq!(TREE, span.to_string());
+
+        let children: Vec<_> = span.children().collect();
+        assert_eq!(2, children.len());
+        assert_eq!("tests::do_stuff", children[0].name());
+        assert_eq!("tests", children[0].record().metadata().target);
+        assert_eq!(
+            vec![
+                &("number".to_string(), RecordValue::Value(52.into())),
+                &("name".to_string(), RecordValue::Value("with spaces".into())),
+            ],
+            children[0].fields().collect::<Vec<_>>()
+        );
+
+        let events: Vec<_> = children[0].events().collect();
+        assert_eq!(Some("here i am!"), events[0].message());
+        assert_eq!("my_crate::db", events[1].metadata().target);
+        assert_eq!("WARN", events[1].metadata().level);
+        assert_eq!(
+            Some(&("error".to_string(), RecordValue::Error("boom".to_string()))),
+            events[1].entries().last()
+        );
+        // the root record doesn't contain its events
+        assert_eq!(0, span.record().entries().count());
+        assert_eq!(6, children[0].record().entries().count());
+    }
+
+    #[test]
+    fn span_names_with_colons_round_trip() {
+        let tree = "tests::root (INFO)\n  my_crate::db::\"pool::acquire\" (INFO)\n  \"spaced target\"::\"with spaces\" (INFO)\n";
+        let span: Span = tree.parse().unwrap();
+        assert_eq!(tree, span.to_string());
+
+        let children: Vec<_> = span.children().collect();
+        assert_eq!("my_crate::db::pool::acquire", children[0].name());
+        assert_eq!("my_crate::db", children[0].record().metadata().target);
+        assert_eq!("pool::acquire", children[0].record().metadata().name);
+        assert_eq!("spaced target", children[1].record().metadata().target);
+        assert_eq!("with spaces", children[1].record().metadata().name);
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let records = Records::new(vec![
+            (
+                "message".to_string(),
+                RecordValue::Debug("here i am!".to_string()),
+            ),
+            ("number".to_string(), RecordValue::Value(52.into())),
+        ]);
+        let text = records.to_string();
+        assert_eq!("message=?\"here i am!\"\nnumber=52\n", text);
+        assert_eq!(records, text.parse().unwrap());
+    }
+
+    #[test]
+    fn parse_errors_point_at_the_line() {
+        let error = "root\n   child".parse::<Span>().unwrap_err();
+        assert_eq!(2, error.line);
+
+        let error = "root\n  - LOUD \"hey\"".parse::<Span>().unwrap_err();
+        assert_eq!("line 2: unknown level `LOUD`", error.to_string());
+
+        let error = "root\nother_root".parse::<Span>().unwrap_err();
+        assert_eq!(2, error.line);
+
+        let error = "root number=".parse::<Span>().unwrap_err();
+        assert_eq!(1, error.line);
+    }
+}
@@ -0,0 +1,69 @@
+//! Span fields that are declared, `tracing::field::Empty` placeholders included, but never get a value.
+use crate::{Location, Report, TestTelemetry};
+use std::fmt::{self, Display};
+
+/// A closed span that never recorded some of its fields, see `Report::unrecorded_fields`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnrecordedFields {
+    pub id: u64,
+    /// Formatted like `UnclosedSpan::path`
+    pub path: String,
+    /// The fields that never got a value, in declaration order
+    pub fields: Vec<String>,
+    pub location: Location,
+}
+
+impl Display for UnrecordedFields {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} closed without a value for `{}` (created {})",
+            self.path,
+            self.fields.join("`, `"),
+            self.location
+        )
+    }
+}
+
+impl Report {
+    /// The closed spans under the root that declared fields they never recorded, in the order they were created.
+    ///
+    /// Spans that are still open are left out, they can still record their fields.
+    pub fn unrecorded_fields(&self) -> Vec<UnrecordedFields> {
+        let mut nodes: Vec<_> = self.node_to_id.iter().collect();
+        nodes.sort();
+        nodes
+            .into_iter()
+            .filter(|(node, _)| self.is_under_root(**node))
+            .filter_map(|(node, id)| {
+                let recorder = self.spans.get(id)?;
+                let metadata = recorder.metadata()?;
+                recorder.closed_sequence()?;
+                let fields = recorder.unrecorded_fields();
+                if fields.is_empty() {
+                    return None;
+                }
+                Some(UnrecordedFields {
+                    id: *id,
+                    path: self.span_path(*node),
+                    fields,
+                    location: metadata.location(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[doc(hidden)]
+pub fn __check_unrecorded_fields(telemetry: &TestTelemetry) -> Result<(), String> {
+    let unrecorded = telemetry.report().unrecorded_fields();
+    if unrecorded.is_empty() {
+        return Ok(());
+    }
+    let spans: Vec<_> = unrecorded.iter().map(|span| format!("  {span}")).collect();
+    Err(format!(
+        "{} span(s) declared fields they never recorded:\n{}",
+        unrecorded.len(),
+        spans.join("\n")
+    ))
+}
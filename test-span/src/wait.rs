@@ -0,0 +1,116 @@
+//! Waiting for telemetry that background tasks emit after the test moved on.
+//!
+//! The layer bumps a generation counter on every span, record, event and close,
+//! which wakes up the waiters so they can look at the report again.
+use crate::{Report, TestTelemetry};
+use once_cell::sync::Lazy;
+use std::fmt::{self, Debug, Display};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+static GENERATION: Lazy<(Mutex<u64>, Condvar)> = Lazy::new(Default::default);
+static NOTIFY: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// Wakes up the waiters, called by the layer once it recorded something.
+pub(crate) fn changed() {
+    let (generation, condvar) = &*GENERATION;
+    *generation.lock().unwrap() += 1;
+    condvar.notify_all();
+    NOTIFY.notify_waiters();
+}
+
+/// The condition of `wait_for` didn't hold before the timeout.
+pub struct WaitTimeout {
+    pub timeout: Duration,
+    /// What was recorded when the wait gave up
+    pub report: Box<Report>,
+    spans: String,
+}
+
+impl WaitTimeout {
+    fn new(timeout: Duration, report: Report, telemetry: &TestTelemetry) -> Self {
+        let spans = report.spans(telemetry.filter()).to_string();
+        Self {
+            timeout,
+            report: Box::new(report),
+            spans,
+        }
+    }
+}
+
+impl Display for WaitTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the telemetry didn't match the condition after {:?}, here is what was recorded\n{}",
+            self.timeout, self.spans
+        )
+    }
+}
+
+impl Debug for WaitTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for WaitTimeout {}
+
+impl TestTelemetry {
+    /// Blocks until `condition` holds for the report, or `timeout` elapses.
+    ///
+    /// The report is looked at again each time something is recorded, on any thread.
+    /// This blocks the thread, so async tests should use `wait_for_async`,
+    /// or the tasks they wait for may never run.
+    pub fn wait_for(
+        &self,
+        condition: impl Fn(&Report) -> bool,
+        timeout: Duration,
+    ) -> Result<Report, WaitTimeout> {
+        let deadline = Instant::now() + timeout;
+        let (generation, condvar) = &*GENERATION;
+        loop {
+            // the condition may record telemetry, so it runs without holding the lock
+            let seen = *generation.lock().unwrap();
+            let report = self.report();
+            if condition(&report) {
+                return Ok(report);
+            }
+
+            let mut current = generation.lock().unwrap();
+            while *current == seen {
+                let now = Instant::now();
+                if now >= deadline {
+                    return Err(WaitTimeout::new(timeout, report, self));
+                }
+                current = condvar.wait_timeout(current, deadline - now).unwrap().0;
+            }
+        }
+    }
+
+    /// Waits until `condition` holds for the report, or `timeout` elapses.
+    ///
+    /// Needs a tokio runtime with the time driver enabled, which `#[tokio::test]` provides.
+    pub async fn wait_for_async(
+        &self,
+        condition: impl Fn(&Report) -> bool,
+        timeout: Duration,
+    ) -> Result<Report, WaitTimeout> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            // registered before looking at the report, so nothing recorded in between is missed
+            let notified = NOTIFY.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            let report = self.report();
+            if condition(&report) {
+                return Ok(report);
+            }
+            if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                return Err(WaitTimeout::new(timeout, report, self));
+            }
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! A watchdog that shows where hanging tests are stuck.
+use crate::layer::ENTERED;
+use crate::report::span_name;
+use crate::{Report, TestTelemetry};
+use daggy::NodeIndex;
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, SystemTime};
+
+/// Prints the spans a test still has open if it runs past a deadline.
+///
+/// `#[test_span(watchdog = "30s")]` starts one for the test, and it is cancelled once dropped.
+/// The output goes straight to stderr, since the test harness only shows what it captured once the test is over.
+pub struct Watchdog {
+    _cancel: mpsc::Sender<()>,
+}
+
+impl Watchdog {
+    pub fn start(test_name: &'static str, telemetry: &TestTelemetry, deadline: Duration) -> Self {
+        Self::start_writing_to(test_name, telemetry, deadline, std::io::stderr())
+    }
+
+    /// Like `start`, but the open spans are written to `output` rather than stderr.
+    pub fn start_writing_to(
+        test_name: &'static str,
+        telemetry: &TestTelemetry,
+        deadline: Duration,
+        mut output: impl Write + Send + 'static,
+    ) -> Self {
+        let (cancel, cancelled) = mpsc::channel::<()>();
+        let telemetry = telemetry.clone();
+        std::thread::Builder::new()
+            .name("test-span-watchdog".to_string())
+            .spawn(move || {
+                if let Err(RecvTimeoutError::Timeout) = cancelled.recv_timeout(deadline) {
+                    let _ = writeln!(
+                        output,
+                        "test-span: `{test_name}` is still running after {deadline:?}, here are its open spans\n{}",
+                        telemetry.report().in_flight()
+                    );
+                }
+            })
+            .expect("couldn't spawn the watchdog thread");
+        Self { _cancel: cancel }
+    }
+}
+
+impl Report {
+    /// Renders the spans that aren't closed yet, with the time since each was last entered.
+    ///
+    /// Spans a thread is currently in are marked with `>`, and the thread is named.
+    pub fn in_flight(&self) -> String {
+        let mut threads: HashMap<u64, Vec<String>> = HashMap::new();
+        for (thread, stack) in ENTERED.lock().unwrap().values() {
+            for id in stack {
+                threads.entry(*id).or_default().push(thread.clone());
+            }
+        }
+
+        let mut rendered = String::new();
+        self.render_in_flight(
+            &mut rendered,
+            self.root_index,
+            0,
+            &threads,
+            SystemTime::now(),
+        );
+        rendered
+    }
+
+    fn render_in_flight(
+        &self,
+        rendered: &mut String,
+        node: NodeIndex,
+        depth: usize,
+        threads: &HashMap<u64, Vec<String>>,
+        now: SystemTime,
+    ) {
+        let Some((&id, recorder)) = self
+            .node_to_id
+            .get(&node)
+            .and_then(|id| Some((id, self.spans.get(id)?)))
+        else {
+            return;
+        };
+
+        // closed spans are skipped, their children are shown in their place
+        let depth = match (recorder.closed_sequence(), recorder.metadata()) {
+            (None, Some(metadata)) => {
+                let entered = threads.get(&id);
+                let since = match recorder.last_entered_at() {
+                    Some(at) => format!(
+                        "last entered {:.1?} ago",
+                        now.duration_since(at).unwrap_or_default()
+                    ),
+                    None => "never entered".to_string(),
+                };
+                let marker = if entered.is_some() { "> " } else { "  " };
+                rendered.push_str(&format!(
+                    "{marker}{}{} ({}) {since}",
+                    "  ".repeat(depth),
+                    span_name(metadata),
+                    metadata.level
+                ));
+                if let Some(entered) = entered {
+                    let threads: Vec<_> =
+                        entered.iter().map(|thread| format!("`{thread}`")).collect();
+                    rendered.push_str(&format!(", entered on thread {}", threads.join(", ")));
+                }
+                rendered.push('\n');
+                depth + 1
+            }
+            _ => depth,
+        };
+
+        for child in self.sorted_children(node) {
+            self.render_in_flight(rendered, child, depth, threads, now);
+        }
+    }
+}
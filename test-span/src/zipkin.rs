@@ -0,0 +1,192 @@
+//! Export a [`Report`] as a [Zipkin v2](https://zipkin.io/zipkin-api/#/default/post_spans) span list.
+//!
+//! The output can be serialized with `serde_json` and posted to a local Zipkin
+//! (`POST http://localhost:9411/api/v2/spans`) to inspect what a test recorded.
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use daggy::NodeIndex;
+use serde::{Deserialize, Serialize};
+
+use crate::log::RecordedEvent;
+use crate::record::{RecordValue, Recorder};
+use crate::report::{Filter, Report};
+
+/// A span, as described by the Zipkin v2 API.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ZipkinSpan {
+    pub trace_id: String,
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<String>,
+    /// The span name, without its target
+    pub name: String,
+    /// Epoch microseconds of the span creation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    /// Microseconds between creation and close, `None` if the span is still open
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<u64>,
+    pub local_endpoint: Endpoint,
+    /// One annotation per event emitted in the span
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<Annotation>,
+    /// The span's recorded fields
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Endpoint {
+    /// The span target
+    pub service_name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Annotation {
+    /// Epoch microseconds of the event
+    pub timestamp: u64,
+    pub value: String,
+}
+
+impl Report {
+    /// Returns the spans that match `filter` as a Zipkin v2 span list.
+    ///
+    /// Spans that are filtered out are skipped,
+    /// their children are attached to the closest exported ancestor.
+    pub fn zipkin(&self, filter: &Filter) -> Vec<ZipkinSpan> {
+        let mut zipkin_spans = Vec::new();
+        if let Some(root) = self.spans.get(&self.root_id) {
+            let trace_id = format_id(self.root_id);
+            zipkin_spans.push(self.zipkin_span(&trace_id, self.root_id, root, None, filter));
+            self.dfs_zipkin_insert(
+                &mut zipkin_spans,
+                &trace_id,
+                self.root_id,
+                self.root_index,
+                filter,
+            );
+        }
+        zipkin_spans
+    }
+
+    fn dfs_zipkin_insert(
+        &self,
+        zipkin_spans: &mut Vec<ZipkinSpan>,
+        trace_id: &str,
+        parent_id: u64,
+        current_node: NodeIndex,
+        filter: &Filter,
+    ) {
+        for child_node in self.sorted_children(current_node) {
+            // spans that are still being recorded are skipped, like `dfs_span_insert` does
+            let Some((&child_id, recorder)) = self.resolve(child_node) else {
+                continue;
+            };
+            let Some(metadata) = recorder.metadata() else {
+                continue;
+            };
+
+            if filter.is_enabled(metadata) {
+                zipkin_spans.push(self.zipkin_span(
+                    trace_id,
+                    child_id,
+                    recorder,
+                    Some(parent_id),
+                    filter,
+                ));
+                self.dfs_zipkin_insert(zipkin_spans, trace_id, child_id, child_node, filter);
+            } else {
+                self.dfs_zipkin_insert(zipkin_spans, trace_id, parent_id, child_node, filter);
+            }
+        }
+    }
+
+    fn zipkin_span(
+        &self,
+        trace_id: &str,
+        span_id: u64,
+        recorder: &Recorder,
+        parent_id: Option<u64>,
+        filter: &Filter,
+    ) -> ZipkinSpan {
+        let contents = recorder.contents(filter);
+        let metadata = contents.metadata();
+
+        let timestamp = recorder.opened_at().map(epoch_micros);
+        let duration = recorder
+            .opened_at()
+            .zip(recorder.closed_at())
+            .map(|(opened, closed)| {
+                closed
+                    .duration_since(opened)
+                    .unwrap_or_default()
+                    .as_micros()
+                    .max(1) as u64
+            });
+
+        ZipkinSpan {
+            trace_id: trace_id.to_string(),
+            id: format_id(span_id),
+            parent_id: parent_id.map(format_id),
+            name: metadata.name,
+            timestamp,
+            duration,
+            local_endpoint: Endpoint {
+                service_name: metadata.target,
+            },
+            annotations: self
+                .logs
+                .events_for_span_id_and_filter(span_id, filter)
+                .map(annotation)
+                .collect(),
+            tags: contents
+                .entries()
+                .map(|(field, value)| (field.clone(), tag_value(value)))
+                .collect(),
+        }
+    }
+}
+
+fn annotation(event: &RecordedEvent) -> Annotation {
+    let mut message = None;
+    let mut fields = Vec::new();
+    for (field, value) in event.visitor.contents() {
+        if field == "message" {
+            message = Some(tag_value(value));
+        } else {
+            fields.push(format!("{field}={}", tag_value(value)));
+        }
+    }
+
+    Annotation {
+        timestamp: epoch_micros(event.timestamp),
+        value: std::iter::once(event.metadata.level.clone())
+            .chain(message)
+            .chain(fields)
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+fn tag_value(value: &RecordValue) -> String {
+    match value {
+        RecordValue::Value(serde_json::Value::String(s))
+        | RecordValue::Debug(s)
+        | RecordValue::Error(s) => s.clone(),
+        RecordValue::Value(other) => other.to_string(),
+    }
+}
+
+// Zipkin ids are lower-hex, 16 characters long.
+fn format_id(id: u64) -> String {
+    format!("{id:016x}")
+}
+
+fn epoch_micros(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64
+}
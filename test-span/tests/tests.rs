@@ -1,6 +1,9 @@
 #[cfg(test)]
 mod traced_span_tests {
-    use test_span::{prelude::*, RecordValue};
+    use test_span::{
+        prelude::*, CallsiteKind, EnterDiagnostic, EventPattern, FieldType, Layer, Location,
+        Notification, RecordValue, Schema, SpanPattern, SpanSchema, ViolationKind,
+    };
     use tracing::Instrument;
 
     #[test_span]
@@ -165,6 +168,1097 @@ mod traced_span_tests {
         insta::assert_json_snapshot!(spans);
     }
 
+    #[test]
+    fn capture_works() {
+        let (number, telemetry) = test_span::capture(|| {
+            let number = do_sync_stuff();
+            assert!(get_logs().contains_message("here i am!"));
+            number
+        });
+
+        assert_eq!(104, number);
+        assert_eq!("test_span::capture", telemetry.spans().name());
+        assert_span!(telemetry.spans(), "do_sync_stuff2", number = 42);
+    }
+
+    #[test_span]
+    fn capture_in_a_span_gets_its_own_root() {
+        let (_, captured) = test_span::capture(do_sync_stuff);
+
+        assert_eq!("test_span::capture", captured.spans().name());
+        assert_span!(captured.spans(), "do_sync_stuff2", number = 42);
+        assert_no_span!(get_spans(), "do_sync_stuff");
+        assert_no_span!(get_spans(), "test_span::capture");
+    }
+
+    #[tokio::test]
+    async fn capture_async_works() {
+        let (number, telemetry) = test_span::capture_async(do_async_stuff()).await;
+
+        assert_eq!(104, number);
+        assert_span!(telemetry.spans(), "do_async_stuff2", number = 42);
+        assert_log!(
+            telemetry.logs(),
+            level = WARN,
+            message = "in a separate context!"
+        );
+    }
+
+    #[test]
+    fn sessions_work() {
+        let session = test_span::start();
+        do_sync_stuff();
+        assert_span_count!(session.telemetry().spans(), "do_sync_stuff", 1);
+        do_sync_stuff();
+        let telemetry = session.finish();
+
+        tracing::info_span!("after_the_session").in_scope(|| {});
+        assert_span_count!(telemetry.spans(), "do_sync_stuff", 2);
+        assert_no_span!(telemetry.spans(), "after_the_session");
+    }
+
+    #[test]
+    fn report_windows_work() {
+        let ((start, end), telemetry) = test_span::capture(|| {
+            let warm = tracing::info_span!("warm_cache").entered();
+            tracing::info!("warming");
+            let start = checkpoint();
+            tracing::info!("still warming");
+            tracing::info_span!("fetch").in_scope(|| tracing::info!("fetched"));
+            drop(warm);
+            let end = checkpoint();
+            tracing::info!("done");
+            (start, end)
+        });
+        let filter = test_span::Filter::new(tracing::Level::INFO);
+        let report = telemetry.report();
+
+        let between = report.between(start, end);
+        assert_eq!(
+            r#"test_span::capture (INFO)
+  - INFO tests::traced_span_tests: "still warming"
+  tests::traced_span_tests::fetch (INFO)
+    - INFO "fetched"
+"#,
+            between.spans(&filter).to_string()
+        );
+        // events of spans opened before the window move to the closest span in it
+        assert_log!(
+            between.logs(&filter),
+            message = "still warming",
+            in_span = "capture"
+        );
+        assert_no_log!(between.logs(&filter), message =~ "^(warming|done)$");
+
+        let since = report.since(end);
+        assert_no_span!(since.spans(&filter), "fetch");
+        assert_log!(since.logs(&filter), message = "done", times = 1);
+    }
+
+    #[test]
+    fn zipkin_export_works() {
+        test_span::init();
+
+        let root_id = {
+            let root_span = test_span::reexports::tracing::span!(::tracing::Level::INFO, "root");
+            let root_id = root_span
+                .id()
+                .expect("couldn't get root span id; this cannot happen.");
+            root_span.in_scope(do_sync_stuff);
+            root_id
+        };
+
+        let zipkin = test_span::Report::from_root(root_id.into_u64())
+            .zipkin(&::test_span::Filter::new(tracing::Level::INFO));
+
+        let names: Vec<_> = zipkin.iter().map(|span| span.name.as_str()).collect();
+        assert_eq!(vec!["root", "do_sync_stuff", "do_sync_stuff2"], names);
+
+        let (root, do_sync_stuff, do_sync_stuff2) = (&zipkin[0], &zipkin[1], &zipkin[2]);
+        assert!(zipkin.iter().all(|span| span.trace_id == root.id));
+        assert!(zipkin.iter().all(|span| span.duration.is_some()));
+        assert_eq!(None, root.parent_id);
+        assert_eq!(Some(&root.id), do_sync_stuff.parent_id.as_ref());
+        assert_eq!(Some(&do_sync_stuff.id), do_sync_stuff2.parent_id.as_ref());
+
+        assert_eq!(
+            "tests::traced_span_tests",
+            do_sync_stuff.local_endpoint.service_name
+        );
+        assert_eq!(
+            "my_crate::an_other_target",
+            do_sync_stuff2.local_endpoint.service_name
+        );
+        assert_eq!(
+            Some("42"),
+            do_sync_stuff2.tags.get("number").map(String::as_str)
+        );
+
+        let annotations: Vec<_> = do_sync_stuff
+            .annotations
+            .iter()
+            .map(|annotation| annotation.value.as_str())
+            .collect();
+        assert_eq!(vec!["INFO here i am!", "INFO number=52"], annotations);
+        // debug events are filtered out
+        assert_eq!(1, do_sync_stuff2.annotations.len());
+
+        let json = serde_json::to_value(&zipkin).unwrap();
+        assert_eq!(
+            json[2]["localEndpoint"]["serviceName"],
+            "my_crate::an_other_target"
+        );
+        assert_eq!(json[0].get("parentId"), None);
+    }
+
+    #[test_span]
+    #[level(tracing::Level::DEBUG)]
+    fn text_snapshot_works() {
+        do_sync_stuff();
+
+        let (spans, logs) = get_telemetry();
+
+        insta::assert_snapshot!(spans.to_string());
+        insta::assert_snapshot!(logs.to_string());
+
+        let parsed: test_span::Span = spans.to_string().parse().unwrap();
+        assert_eq!(spans.to_string(), parsed.to_string());
+        test_span::assert_spans_eq!(parsed, spans);
+        let parsed: test_span::Records = logs.to_string().parse().unwrap();
+        assert_eq!(logs, parsed);
+    }
+
+    #[test_span(tokio::test)]
+    async fn span_patterns_work() {
+        futures::join!(do_async_stuff(), do_async_trace_stuff());
+
+        let spans = get_spans();
+
+        // do_async_trace_stuff is filtered out, its children show up under the root
+        let stuff = SpanPattern::new("do_async_stuff")
+            .event(EventPattern::new().message("here i am!"))
+            .child(SpanPattern::new("do_async_stuff2").field("number", 42));
+        test_span::assert_spans_match!(
+            spans,
+            SpanPattern::new("span_patterns_work")
+                .child(stuff.clone())
+                .child(stuff.clone())
+                .child(SpanPattern::new("do_async_stuff2"))
+        );
+
+        let unexpected = SpanPattern::new("span_patterns_work").descendant(
+            SpanPattern::new("do_async_stuff")
+                .event(EventPattern::new().level(tracing::Level::ERROR)),
+        );
+        assert!(unexpected.matches(&spans).is_err());
+    }
+
+    #[test_span]
+    fn span_tree_macro_works() {
+        do_sync_stuff();
+        do_sync_stuff();
+
+        let spans = get_spans();
+
+        let number = 52;
+        assert_spans_match!(
+            spans,
+            span_tree! {
+                "span_tree_macro_works" {
+                    "tests::traced_span_tests::do_sync_stuff" {
+                        event!(INFO, "here i am!"),
+                        event!(INFO, number = number),
+                        "do_sync_stuff2"(number = 42) {
+                            event!(INFO, "here i am again!")
+                        }
+                    }
+                    #[ordered]
+                    _ {
+                        .. "my_crate::an_other_target::do_sync_stuff2"
+                    }
+                }
+            }
+        );
+
+        assert!(span_tree! { "span_tree_macro_works" { "do_sync_stuff2" } }
+            .matches(&spans)
+            .is_err());
+        assert!(
+            span_tree! { "span_tree_macro_works" { "do_sync_stuff" { event!(DEBUG) } } }
+                .matches(&spans)
+                .is_err()
+        );
+    }
+
+    #[test_span]
+    fn log_assertions_work() {
+        do_sync_stuff();
+        do_sync_stuff();
+
+        let logs = get_logs();
+
+        assert_log!(
+            logs,
+            level = INFO,
+            target = "tests",
+            in_span = "do_sync_stuff2",
+            message =~ r"^here i am \w+!$",
+            times = 2,
+        );
+        assert_log!(logs, in_span = "do_sync_stuff", number = 52u64, times = 2);
+        assert_no_log!(logs, level = DEBUG);
+        assert_no_log!(logs, message = "here i am!", in_span = "do_sync_stuff2");
+
+        let missing = std::panic::catch_unwind(|| assert_log!(logs, level = WARN));
+        assert!(missing.is_err());
+    }
+
+    #[test_span]
+    fn span_assertions_work() {
+        do_sync_stuff();
+        do_sync_stuff();
+
+        let spans = get_spans();
+
+        assert_span!(spans, "do_sync_stuff");
+        assert_span_count!(spans, "do_sync_stuff2", 2, number = 42);
+        assert_span_count!(spans, "do_sync_stuff2", 1, in_span = "do_sync_stuff");
+        assert_no_span!(spans, "do_sync_stuff", in_span = "do_sync_stuff2");
+
+        let too_many = std::panic::catch_unwind(|| assert_span_count!(spans, "do_sync_stuff", ..2));
+        assert!(too_many.is_err());
+    }
+
+    #[test_span]
+    fn ordering_assertions_work() {
+        do_sync_stuff();
+        tracing::info!("all done");
+
+        let report = get_report();
+
+        assert_before!(
+            report,
+            span("do_sync_stuff2").closed,
+            span("do_sync_stuff").closed
+        );
+        assert_before!(report, event("here i am!"), span("do_sync_stuff2").opened);
+        assert_before!(report, span("do_sync_stuff").closed, event("all done"));
+        assert_before!(
+            report,
+            event(EventPattern::new().in_span("do_sync_stuff2")),
+            event(EventPattern::new().field("number", 52u64))
+        );
+
+        let reversed = std::panic::catch_unwind(|| {
+            assert_before!(report, event("all done"), span("do_sync_stuff").opened)
+        });
+        assert!(reversed.is_err());
+        let never = std::panic::catch_unwind(|| {
+            assert_before!(report, span("do_sync_stuff").opened, event("never logged"))
+        });
+        assert!(never.is_err());
+    }
+
+    #[test_span]
+    fn ordering_assertions_stay_under_the_root() {
+        let first = tracing::info_span!("first");
+        first.in_scope(do_sync_stuff);
+        tracing::info_span!("outside").in_scope(|| tracing::info!("next door"));
+
+        let report = get_report_for_root(&first.id().unwrap());
+        assert_before!(report, span("do_sync_stuff").opened, event("here i am!"));
+        let outside = std::panic::catch_unwind(|| {
+            assert_before!(report, span("do_sync_stuff").opened, span("outside").opened)
+        });
+        assert!(outside.is_err());
+        let next_door = std::panic::catch_unwind(|| {
+            assert_before!(report, span("do_sync_stuff").closed, event("next door"))
+        });
+        assert!(next_door.is_err());
+    }
+
+    #[test_span(tokio::test)]
+    async fn concurrency_assertions_work() {
+        futures::join!(do_async_stuff(), do_async_stuff());
+        do_async_stuff().await;
+
+        let report = get_report();
+        let spans = get_spans();
+
+        let joined: Vec<u64> = spans.children().take(2).map(|span| span.id()).collect();
+        assert_eq!(Some(true), report.overlapped(joined[0], joined[1]));
+        let awaited = spans.children().nth(2).unwrap().id();
+        assert_eq!(Some(false), report.overlapped(joined[1], awaited));
+
+        assert_concurrent!(report, "do_async_stuff2", "concurrency_assertions_work");
+        assert_sequential!(report, "do_async_stuff2");
+
+        let not_all_concurrent =
+            std::panic::catch_unwind(|| assert_concurrent!(report, "do_async_stuff"));
+        assert!(not_all_concurrent.is_err());
+        let not_all_sequential =
+            std::panic::catch_unwind(|| assert_sequential!(report, "do_async_stuff"));
+        assert!(not_all_sequential.is_err());
+    }
+
+    #[test_span]
+    fn concurrency_assertions_stay_under_the_root() {
+        let first = tracing::info_span!("first");
+        // a sibling of `first`, active while the spans under it run
+        let outside = tracing::info_span!("do_sync_stuff");
+        let entered = outside.enter();
+        first.in_scope(|| {
+            do_sync_stuff();
+            do_sync_stuff();
+        });
+        drop(entered);
+
+        let report = get_report_for_root(&first.id().unwrap());
+        assert_sequential!(report, "do_sync_stuff");
+        let everywhere = get_report();
+        let overlapping =
+            std::panic::catch_unwind(|| assert_sequential!(everywhere, "do_sync_stuff"));
+        assert!(overlapping.is_err());
+    }
+
+    #[test_span(snapshot)]
+    #[level(tracing::Level::DEBUG)]
+    fn auto_snapshot_works() {
+        do_sync_stuff();
+    }
+
+    #[test_span(tokio::test)]
+    #[snapshot(spans, format = "text", sorted)]
+    async fn text_auto_snapshot_works() {
+        futures::join!(do_async_stuff(), do_async_trace_stuff());
+    }
+
+    #[test_span]
+    #[level(tracing::Level::DEBUG)]
+    #[root(name = "checkout_flow", level = WARN, fields(test = "checkout", attempt = 1))]
+    fn root_attribute_works() {
+        do_sync_stuff();
+
+        let spans = get_spans();
+        assert_eq!("tests::traced_span_tests::checkout_flow", spans.name());
+        assert_eq!("WARN", spans.record().metadata().level);
+        assert_span!(spans, "checkout_flow", test = "checkout", attempt = 1u64);
+    }
+
+    #[test_span]
+    #[snapshot(format = "text", backend = "golden", redact("number"))]
+    fn golden_snapshot_works() {
+        do_sync_stuff();
+    }
+
+    #[test_span]
+    #[should_panic(expected = "the dump doesn't swallow the panic")]
+    fn failure_dump_works_with_should_panic() {
+        do_sync_stuff();
+        panic!("the dump doesn't swallow the panic");
+    }
+
+    #[test]
+    fn failure_dump_is_written_to_the_artifacts_dir() {
+        let dir = std::env::temp_dir().join(format!("test-span-artifacts-{}", std::process::id()));
+        // runs the test above in its own process, so the variable doesn't leak into other tests
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args([
+                "traced_span_tests::failure_dump_works_with_should_panic",
+                "--exact",
+            ])
+            .env(test_span::ARTIFACTS_DIR, &dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+
+        let dump = std::fs::read_to_string(
+            dir.join("tests__traced_span_tests__failure_dump_works_with_should_panic.txt"),
+        )
+        .unwrap();
+        assert!(dump.starts_with(
+            "spans:\ntests::traced_span_tests::failure_dump_works_with_should_panic (INFO)"
+        ));
+        assert!(dump.contains("do_sync_stuff"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test_span(tokio::test)]
+    #[should_panic(expected = "async tests are dumped too")]
+    async fn failure_dump_works_with_async_tests() {
+        do_async_stuff().await;
+        panic!("async tests are dumped too");
+    }
+
+    #[test_span]
+    #[level(tracing::Level::DEBUG)]
+    fn telemetry_handle_works() {
+        let before = telemetry.checkpoint();
+        do_sync_stuff();
+        let after = telemetry.checkpoint();
+        assert_span!(telemetry.since(before).spans(), "do_sync_stuff");
+        assert_no_span!(telemetry.since(after).spans(), "do_sync_stuff");
+
+        assert!(telemetry.logs().contains_message("debug: here i am again!"));
+        let info = telemetry.with_filter(test_span::Filter::new(tracing::Level::INFO));
+        assert!(!info.logs().contains_message("debug: here i am again!"));
+        assert!(info.logs().contains_message("here i am again!"));
+
+        // the shim and the free functions see the same run
+        assert_eq!(telemetry.spans(), get_spans());
+        assert_eq!(telemetry.root_id(), TestTelemetry::current().root_id());
+
+        let names: Vec<_> = info.zipkin().into_iter().map(|span| span.name).collect();
+        assert_eq!(
+            vec!["telemetry_handle_works", "do_sync_stuff", "do_sync_stuff2"],
+            names
+        );
+    }
+
+    #[test_span]
+    fn checkpoints_work() {
+        do_sync_stuff();
+        let warm = telemetry.checkpoint();
+        do_sync_stuff_2(1);
+        let request = checkpoint();
+        do_sync_stuff();
+
+        let warm_phase = telemetry.between(warm, request);
+        assert_span_count!(warm_phase.spans(), "do_sync_stuff2", 1, number = 1);
+        assert_no_span!(warm_phase.spans(), "do_sync_stuff");
+
+        let request_phase = telemetry.since(request);
+        assert_span_count!(request_phase.spans(), "do_sync_stuff", 1);
+        assert_no_span!(request_phase.spans(), "do_sync_stuff2", number = 1);
+        assert_span_count!(get_spans(), "do_sync_stuff", 2);
+    }
+
+    #[test_span]
+    fn wait_for_works() {
+        let current = tracing::Span::current();
+        let background = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            current.in_scope(|| tracing::info_span!("flush").in_scope(|| {}));
+        });
+
+        let report = telemetry
+            .wait_for(
+                |report| report.contains_span("flush"),
+                std::time::Duration::from_secs(5),
+            )
+            .unwrap();
+        assert!(report.contains_span("traced_span_tests::flush"));
+        background.join().unwrap();
+
+        // recorded next to the span the report is rooted at, rather than under it
+        let first = tracing::info_span!("first");
+        tracing::info_span!("sibling").in_scope(|| {});
+        let sibling = test_span::TestTelemetry::new(
+            first.id().unwrap(),
+            test_span::Filter::new(tracing::Level::INFO),
+        )
+        .wait_for(
+            |report| report.contains_span("sibling"),
+            std::time::Duration::from_millis(10),
+        );
+        assert!(sibling.is_err());
+
+        let timeout = telemetry
+            .wait_for(
+                |report| report.contains_span("never"),
+                std::time::Duration::from_millis(10),
+            )
+            .err()
+            .expect("the span is never recorded");
+        assert!(timeout
+            .to_string()
+            .starts_with("the telemetry didn't match the condition after 10ms"));
+    }
+
+    #[test_span]
+    fn wait_for_walks_the_tree_while_spans_are_created() {
+        let current = tracing::Span::current();
+        let background = std::thread::spawn(move || {
+            current.in_scope(|| {
+                for attempt in 0..200u64 {
+                    tracing::info_span!("attempt", attempt).in_scope(|| {
+                        tracing::info_span!("nested").in_scope(|| tracing::info!("working"));
+                    });
+                }
+            });
+        });
+
+        telemetry
+            .wait_for(
+                |report| {
+                    report
+                        .spans(&test_span::Filter::new(tracing::Level::TRACE))
+                        .children()
+                        .flat_map(test_span::Span::children)
+                        .count()
+                        == 200
+                },
+                std::time::Duration::from_secs(5),
+            )
+            .unwrap();
+        background.join().unwrap();
+        assert_span_count!(get_spans(), "nested", 200);
+    }
+
+    #[test_span(tokio::test)]
+    async fn wait_for_async_works() {
+        tokio::spawn(
+            async {
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                tracing::info_span!("flush").in_scope(|| {});
+            }
+            .instrument(tracing::Span::current()),
+        );
+
+        telemetry
+            .wait_for_async(
+                |report| report.contains_span("flush"),
+                std::time::Duration::from_secs(5),
+            )
+            .await
+            .unwrap();
+        assert_span!(get_spans(), "flush");
+    }
+
+    #[test_span(tokio::test)]
+    async fn subscriptions_work() {
+        let mut subscription = telemetry.subscribe();
+        let retry = tokio::spawn(
+            async {
+                let span = tracing::info_span!("retry", attempt = tracing::field::Empty);
+                span.record("attempt", 2);
+                span.in_scope(|| tracing::warn!("retrying"));
+            }
+            .instrument(tracing::Span::current()),
+        );
+
+        let Some(Notification::SpanOpened { id, name, fields }) = subscription.next().await else {
+            panic!("expected the retry span to open first");
+        };
+        assert_eq!("tests::traced_span_tests::retry", name);
+        assert!(fields.is_empty());
+        assert_eq!(
+            Some(Notification::FieldsRecorded {
+                id,
+                name: name.clone(),
+                fields: vec![("attempt".to_string(), RecordValue::Value(2.into()))],
+            }),
+            subscription.next().await
+        );
+        let Some(Notification::EventEmitted { span_id, event }) = subscription.next().await else {
+            panic!("expected the warning");
+        };
+        assert_eq!((id, Some("retrying")), (span_id, event.message()));
+        assert_eq!(
+            Some(Notification::SpanClosed { id, name }),
+            subscription.next().await
+        );
+        retry.await.unwrap();
+    }
+
+    #[test]
+    fn subscriptions_follow_a_span_to_its_root() {
+        let session = test_span::start();
+        let child = tracing::info_span!("child");
+        let subscription = Layer::subscribe(&child.id().unwrap());
+        child.in_scope(|| tracing::info!("in the child"));
+        drop(child);
+        let telemetry = session.finish();
+
+        assert_eq!(3, subscription.count());
+        // the root is closed by now, nothing can be recorded under it
+        assert_eq!(0, telemetry.subscribe().count());
+    }
+
+    #[test]
+    fn blocking_subscriptions_end_with_the_root() {
+        let session = test_span::start();
+        let subscription = session.telemetry().subscribe();
+        tracing::info!("one");
+        tracing::info_span!("two").in_scope(|| {});
+        session.finish();
+
+        let names: Vec<_> = subscription
+            .map(|notification| match notification {
+                Notification::EventEmitted { event, .. } => event.message().unwrap().to_string(),
+                Notification::SpanOpened { name, .. } => format!("opened {name}"),
+                Notification::SpanClosed { name, .. } => format!("closed {name}"),
+                Notification::FieldsRecorded { name, .. } => format!("recorded {name}"),
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                "one",
+                "opened tests::traced_span_tests::two",
+                "closed tests::traced_span_tests::two",
+                "closed test_span::capture"
+            ],
+            names
+        );
+    }
+
+    /// Shares what is written to it, so a test can read it from an other thread.
+    #[derive(Clone, Default)]
+    struct Captured(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Captured {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    #[test_span]
+    fn watchdog_prints_the_open_spans_past_the_deadline() {
+        let output = Captured::default();
+        let _watchdog = test_span::Watchdog::start_writing_to(
+            "tests::hanging",
+            telemetry,
+            std::time::Duration::from_millis(20),
+            output.clone(),
+        );
+
+        // hangs until the watchdog fires
+        tracing::info_span!("stuck").in_scope(|| {
+            let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+            while output.contents().is_empty() && std::time::Instant::now() < deadline {
+                std::thread::sleep(std::time::Duration::from_millis(5));
+            }
+        });
+
+        let dump = output.contents();
+        assert!(
+            dump.starts_with(
+                "test-span: `tests::hanging` is still running after 20ms, here are its open spans\n\
+                 > tests::traced_span_tests::watchdog_prints_the_open_spans_past_the_deadline (INFO) last entered"
+            ),
+            "{dump}"
+        );
+        assert!(
+            dump.contains(">   tests::traced_span_tests::stuck (INFO) last entered"),
+            "{dump}"
+        );
+    }
+
+    #[test_span]
+    fn watchdogs_dropped_before_the_deadline_stay_quiet() {
+        let output = Captured::default();
+        drop(test_span::Watchdog::start_writing_to(
+            "tests::quick",
+            telemetry,
+            std::time::Duration::from_millis(20),
+            output.clone(),
+        ));
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert_eq!("", output.contents());
+    }
+
+    #[test_span(tokio::test, watchdog = "30s")]
+    async fn watchdog_works() {
+        let pending = tracing::info_span!("pending");
+        let closed = tracing::info_span!("closed");
+        closed.in_scope(|| {});
+        drop(closed);
+        let in_flight = async {
+            let _waiting = tracing::info_span!("waiting").entered();
+            get_report().in_flight()
+        }
+        .instrument(tracing::info_span!("request"))
+        .await;
+
+        let lines: Vec<_> = in_flight.lines().collect();
+        assert_eq!(4, lines.len(), "{in_flight}");
+        assert!(
+            lines[0].starts_with("> tests::traced_span_tests::watchdog_works (INFO) last entered")
+        );
+        assert!(lines[1].starts_with("    tests::traced_span_tests::pending (INFO) never entered"));
+        assert!(lines[2].starts_with(">   tests::traced_span_tests::request (INFO) last entered"));
+        assert!(lines[3].starts_with(">     tests::traced_span_tests::waiting (INFO) last entered"));
+        assert!(lines[3].ends_with(&format!(
+            ", entered on thread `{}`",
+            std::thread::current().name().unwrap()
+        )));
+        drop(pending);
+    }
+
+    #[test_span(deny_unclosed_spans)]
+    fn unclosed_spans_are_reported() {
+        let leaked = tracing::info_span!("leaked");
+        leaked.in_scope(|| tracing::info_span!("closed").in_scope(|| {}));
+
+        let unclosed = get_report().unclosed_spans();
+        assert_eq!(1, unclosed.len());
+        assert_eq!(
+            "tests::traced_span_tests::unclosed_spans_are_reported/tests::traced_span_tests::leaked",
+            unclosed[0].path
+        );
+        assert_eq!(Some(file!()), unclosed[0].location.file.as_deref());
+        // the span is closed before the test returns, deny_unclosed_spans lets it go
+        drop(leaked);
+    }
+
+    #[test_span(tokio::test, deny_unclosed_spans)]
+    #[should_panic(expected = "1 span(s) weren't closed when the test returned:\n  \
+        tests::traced_span_tests::deny_unclosed_spans_works/tests::traced_span_tests::background created at test-span/tests/tests.rs:")]
+    async fn deny_unclosed_spans_works() {
+        let background = tracing::info_span!("background");
+        std::mem::forget(background);
+    }
+
+    #[test_span]
+    fn misplaced_exits_are_reported() {
+        let (outer, outer_line) = (tracing::info_span!("outer"), line!());
+        let inner = tracing::info_span!("inner");
+        let outer_guard = outer.enter();
+        let inner_guard = inner.enter();
+        drop(outer_guard);
+        drop(inner_guard);
+
+        // what a guard held across an .await ends up doing on a multi threaded runtime
+        let (moved, moved_line) = (tracing::info_span!("moved"), line!());
+        let id = moved.id().unwrap();
+        let dispatch = tracing::dispatcher::get_default(|dispatch| dispatch.clone());
+        dispatch.enter(&id);
+        let other_thread = dispatch.clone();
+        let exited = id.clone();
+        std::thread::Builder::new()
+            .name("other".to_string())
+            .spawn(move || other_thread.exit(&exited))
+            .unwrap()
+            .join()
+            .unwrap();
+        // the registry only lets go of it on the thread it was entered on
+        dispatch.exit(&id);
+
+        let thread = std::thread::current().name().unwrap().to_string();
+        assert_eq!(
+            vec![
+                EnterDiagnostic::ExitedOutOfOrder {
+                    span: "tests::traced_span_tests::misplaced_exits_are_reported/tests::traced_span_tests::outer".to_string(),
+                    location: Location {
+                        file: Some(file!().to_string()),
+                        line: Some(outer_line),
+                    },
+                    thread: thread.clone(),
+                    still_entered: vec!["tests::traced_span_tests::inner".to_string()],
+                },
+                EnterDiagnostic::ExitedOnAnotherThread {
+                    span: "tests::traced_span_tests::misplaced_exits_are_reported/tests::traced_span_tests::moved".to_string(),
+                    location: Location {
+                        file: Some(file!().to_string()),
+                        line: Some(moved_line),
+                    },
+                    entered_on: thread,
+                    exited_on: "other".to_string(),
+                },
+            ],
+            get_report().enter_diagnostics()
+        );
+    }
+
+    #[test_span]
+    fn enter_diagnostics_stay_under_the_root() {
+        let first = tracing::info_span!("first");
+        first.in_scope(do_sync_stuff);
+
+        let outer = tracing::info_span!("outer");
+        let inner = tracing::info_span!("inner");
+        let outer_guard = outer.enter();
+        let inner_guard = inner.enter();
+        drop(outer_guard);
+        drop(inner_guard);
+
+        assert_eq!(1, get_report().enter_diagnostics().len());
+        assert_eq!(
+            Vec::<EnterDiagnostic>::new(),
+            get_report_for_root(&first.id().unwrap()).enter_diagnostics()
+        );
+    }
+
+    #[test_span(deny_misplaced_exits)]
+    #[should_panic(expected = "spans were exited out of place:\n  \
+        `tests::traced_span_tests::deny_misplaced_exits_works/tests::traced_span_tests::outer` (created at test-span/tests/tests.rs:")]
+    fn deny_misplaced_exits_works() {
+        let outer = tracing::info_span!("outer");
+        let inner = tracing::info_span!("inner");
+        let outer_guard = outer.enter();
+        let inner_guard = inner.enter();
+        drop(outer_guard);
+        drop(inner_guard);
+    }
+
+    #[test_span]
+    fn unrecorded_fields_are_reported() {
+        let request = tracing::info_span!(
+            "request",
+            method = "GET",
+            status = tracing::field::Empty,
+            latency = tracing::field::Empty
+        );
+        request.record("latency", 12);
+        drop(request);
+        let open = tracing::info_span!("open", status = tracing::field::Empty);
+
+        let unrecorded = get_report().unrecorded_fields();
+        assert_eq!(1, unrecorded.len());
+        assert_eq!(
+            "tests::traced_span_tests::unrecorded_fields_are_reported/tests::traced_span_tests::request",
+            unrecorded[0].path
+        );
+        assert_eq!(vec!["status".to_string()], unrecorded[0].fields);
+        // open spans can still record their fields
+        drop(open);
+    }
+
+    #[test_span]
+    fn unrecorded_fields_stay_under_the_root() {
+        let first = tracing::info_span!("first");
+        first.in_scope(do_sync_stuff);
+        tracing::info_span!("outside", status = tracing::field::Empty).in_scope(|| {});
+
+        assert_eq!(1, get_report().unrecorded_fields().len());
+        assert!(get_report_for_root(&first.id().unwrap())
+            .unrecorded_fields()
+            .is_empty());
+    }
+
+    #[test_span(deny_unrecorded_fields)]
+    #[should_panic(expected = "1 span(s) declared fields they never recorded:\n  \
+        tests::traced_span_tests::deny_unrecorded_fields_works/tests::traced_span_tests::request closed without a value for `status` (created at test-span/tests/tests.rs:")]
+    fn deny_unrecorded_fields_works() {
+        tracing::info_span!("request", status = tracing::field::Empty).in_scope(|| {});
+    }
+
+    #[test_span]
+    fn schemas_are_validated() {
+        let schema = Schema::new()
+            .span(
+                SpanSchema::named("http.request")
+                    .required("http.method", FieldType::String)
+                    .required("http.status_code", FieldType::Integer)
+                    .optional("http.route", FieldType::String)
+                    .forbidden("password"),
+            )
+            .span(
+                SpanSchema::in_target("tests::traced_span_tests")
+                    .optional("subgraph.name", FieldType::String),
+            );
+
+        tracing::info_span!(
+            "http.request",
+            http.method = "GET",
+            http.route = "/",
+            http.status_code = 200
+        )
+        .in_scope(|| {});
+        assert_schema!(get_report(), schema);
+
+        let line = line!() + 1;
+        tracing::info_span!(
+            "http.request",
+            http.method = "POST",
+            http.route = 404,
+            password = "hunter2"
+        )
+        .in_scope(|| {});
+        let violations = schema.validate(&get_report());
+        let kinds: Vec<_> = violations
+            .iter()
+            .map(|violation| (violation.field.as_str(), violation.kind.clone()))
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    "http.status_code",
+                    ViolationKind::Missing {
+                        expected: FieldType::Integer
+                    }
+                ),
+                (
+                    "http.route",
+                    ViolationKind::WrongType {
+                        expected: FieldType::String,
+                        actual: RecordValue::Value(404.into())
+                    }
+                ),
+                ("password", ViolationKind::Forbidden),
+            ],
+            kinds
+        );
+        assert_eq!(
+            format!("tests::traced_span_tests::schemas_are_validated/tests::traced_span_tests::http.request (created at {}:{line}): `http.route` should be string, got 404", file!()),
+            violations[1].to_string()
+        );
+    }
+
+    #[test_span]
+    fn schemas_stay_under_the_root() {
+        let schema = Schema::new().span(SpanSchema::named("outside").forbidden("password"));
+        let first = tracing::info_span!("first");
+        first.in_scope(do_sync_stuff);
+        tracing::info_span!("outside", password = "hunter2").in_scope(|| {});
+
+        assert_eq!(1, schema.validate(&get_report()).len());
+        assert_schema!(get_report_for_root(&first.id().unwrap()), schema);
+    }
+
+    #[test_span]
+    #[should_panic(expected = "1 field(s) don't abide by the schema:\n  \
+        tests::traced_span_tests::assert_schema_works/tests::traced_span_tests::subgraph.fetch (created at test-span/tests/tests.rs:")]
+    fn assert_schema_works() {
+        let schema = Schema::from_json(
+            r#"{ "spans": [{ "name": "subgraph.fetch", "required": { "subgraph.name": "string" } }] }"#,
+        )
+        .unwrap();
+        tracing::info_span!("subgraph.fetch").in_scope(|| {});
+        assert_schema!(get_report(), schema);
+    }
+
+    #[test_span]
+    fn coverage_counts_callsite_hits() {
+        for _ in 0..3 {
+            tracing::info_span!("covered").in_scope(|| {});
+        }
+
+        let coverage = Layer::coverage();
+        let covered = coverage
+            .callsites
+            .iter()
+            .find(|callsite| callsite.name == "covered")
+            .unwrap();
+        assert_eq!(CallsiteKind::Span, covered.kind);
+        assert_eq!("tests::traced_span_tests", covered.target);
+        assert_eq!(Some(file!()), covered.file.as_deref());
+        assert_eq!(3, covered.hits);
+        assert!(!coverage
+            .unexercised()
+            .iter()
+            .any(|callsite| callsite.name == "covered"));
+        assert!(coverage.to_json().contains("\"name\": \"covered\""));
+    }
+
+    #[test_span]
+    fn coverage_lists_callsites_the_levels_filter_out() {
+        // registered and reached, but the test only records INFO and above
+        let line = line!() + 1;
+        tracing::debug!("filtered out");
+
+        let coverage = Layer::coverage();
+        let unexercised = coverage.unexercised();
+        let filtered = unexercised
+            .iter()
+            .find(|callsite| {
+                callsite.line == Some(line) && callsite.file.as_deref() == Some(file!())
+            })
+            .expect("the debug event isn't listed as unexercised");
+        assert_eq!(CallsiteKind::Event, filtered.kind);
+        assert_eq!((0, 1), (filtered.hits, filtered.filtered));
+        assert!(coverage.summary().contains(&format!(
+            "  event tests::traced_span_tests (DEBUG) at {}:{line}, filtered out 1 time(s) by the tests' levels\n",
+            file!()
+        )));
+    }
+
+    #[test]
+    fn coverage_reports_are_merged_into_the_coverage_dir() {
+        let dir = std::env::temp_dir().join(format!("test-span-coverage-{}", std::process::id()));
+        // runs the tests in their own processes, like a runner that gives each test a process would
+        for test in [
+            "traced_span_tests::coverage_counts_callsite_hits",
+            "traced_span_tests::coverage_lists_callsites_the_levels_filter_out",
+        ] {
+            let output = std::process::Command::new(std::env::current_exe().unwrap())
+                .args([test, "--exact"])
+                .env(test_span::COVERAGE_DIR, &dir)
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+        }
+
+        let binary = std::env::current_exe().unwrap();
+        let binary = binary.file_stem().unwrap().to_string_lossy();
+        let json = std::fs::read_to_string(dir.join(format!("{binary}.coverage.json"))).unwrap();
+        let summary = std::fs::read_to_string(dir.join(format!("{binary}.coverage.txt"))).unwrap();
+        // the first process' callsite is still there once the second one wrote its report
+        assert!(json.contains("\"name\": \"covered\""));
+        assert!(summary.contains("filtered out 1 time(s) by the tests' levels"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test_span(deny_misplaced_exits)]
+    fn deny_misplaced_exits_lets_nested_guards_go() {
+        let outer = tracing::info_span!("outer");
+        let _outer = outer.enter();
+        tracing::info_span!("inner").in_scope(|| tracing::info!("nested"));
+    }
+
+    #[test_span]
+    #[rstest::rstest]
+    #[case(1)]
+    #[case(2)]
+    fn rstest_cases_work(#[case] number: u64) {
+        tracing::info!(number);
+        assert_log!(get_logs(), number = number, times = 1);
+    }
+
+    #[test_span]
+    #[rstest::rstest]
+    #[case(3)]
+    fn rstest_cases_keep_mutable_arguments(#[case] mut number: u64) {
+        number += 1;
+        tracing::info!(number);
+        assert_log!(get_logs(), number = 4u64);
+    }
+
+    #[test_span(tokio::test)]
+    #[rstest::rstest]
+    #[case((42, 52))]
+    async fn rstest_async_cases_work(#[case] (number, expected): (u8, u8)) {
+        assert_eq!(expected, do_async_stuff_2(number).await);
+        assert_span!(get_spans(), "do_async_stuff2", number = number);
+    }
+
+    #[test_span]
+    #[rstest::rstest]
+    #[case(52u8)]
+    #[case("52")]
+    fn rstest_generic_cases_work<T>(#[case] number: T)
+    where
+        T: std::fmt::Display,
+    {
+        tracing::info!(number = %number);
+        assert_log!(get_logs(), number = "52");
+    }
+
+    #[test_span]
+    #[test_case::test_case(42 ; "forty two")]
+    fn test_case_works(number: u8) {
+        assert_eq!(52, do_sync_stuff_2(number));
+        assert_span!(get_spans(), "do_sync_stuff2");
+    }
+
+    #[test_case::test_case(42)]
+    #[test_span(tokio::test)]
+    async fn test_case_async_works(number: u8) {
+        assert_eq!(52, do_async_stuff_2(number).await);
+        assert_span!(get_spans(), "do_async_stuff2", number = number);
+    }
+
     #[test_span]
     fn get_all_logs_to_have_thread_support() {
         std::thread::spawn(|| {
@@ -0,0 +1,6 @@
+// Checks the diagnostics of test-span's macros, run with TRYBUILD=overwrite to update them.
+#[test]
+fn ui() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+}
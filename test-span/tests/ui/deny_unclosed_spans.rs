@@ -0,0 +1,6 @@
+use test_span::prelude::*;
+
+#[test_span(deny_unclosed_spans = true)]
+fn with_a_value() {}
+
+fn main() {}
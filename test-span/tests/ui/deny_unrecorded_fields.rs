@@ -0,0 +1,6 @@
+use test_span::prelude::*;
+
+#[test_span(deny_unrecorded_fields = true)]
+fn with_a_value() {}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use test_span::prelude::*;
+
+#[test_span]
+#[level(tracing::Level::INFO)]
+#[level(tracing::Level::DEBUG)]
+fn two_levels() {}
+
+fn main() {}
@@ -0,0 +1,7 @@
+use test_span::prelude::*;
+
+#[test_span]
+#[level("info")]
+fn level_is_a_string() {}
+
+fn main() {}
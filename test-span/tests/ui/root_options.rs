@@ -0,0 +1,16 @@
+use test_span::prelude::*;
+
+#[test_span]
+#[root(name = "checkout_flow", level = VERBOSE)]
+fn unknown_root_level() {}
+
+#[test_span]
+#[root(label = "checkout_flow")]
+fn unknown_root_option() {}
+
+#[test_span]
+#[root(name = "checkout_flow")]
+#[root(level = INFO)]
+fn two_roots() {}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use test_span::prelude::*;
+
+#[test_span(snapshot(format = "yaml"))]
+fn unknown_format() {}
+
+#[test_span]
+#[snapshot(spans, compress)]
+fn unknown_option() {}
+
+fn main() {}
@@ -0,0 +1,7 @@
+use test_span::prelude::*;
+
+fn main() {
+    let _ = span_tree! { .. "root" };
+    let _ = span_tree! { "root" { event!(VERBOSE, "message") } };
+    let _ = span_tree! { root };
+}
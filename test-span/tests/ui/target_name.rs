@@ -0,0 +1,8 @@
+use test_span::prelude::*;
+
+#[test_span]
+#[target("my-crate" = tracing::Level::DEBUG)]
+#[target(my_crate = debug)]
+fn bad_targets() {}
+
+fn main() {}
@@ -0,0 +1,7 @@
+use test_span::prelude::*;
+
+#[test_span]
+#[target(my_crate)]
+fn target_without_level() {}
+
+fn main() {}
@@ -0,0 +1,7 @@
+use test_span::prelude::*;
+
+#[test_span]
+#[level(tracing::Level::VERBOSE)]
+fn unknown_level() {}
+
+fn main() {}
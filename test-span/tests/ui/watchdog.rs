@@ -0,0 +1,9 @@
+use test_span::prelude::*;
+
+#[test_span(watchdog = "30 seconds")]
+fn unknown_unit() {}
+
+#[test_span(watchdog = 30)]
+fn not_a_string() {}
+
+fn main() {}